@@ -3,5 +3,5 @@ use rusty_merkle_tree::util;
 fn main() {
     let path = "examples/strings_example.txt";
 
-    util::run_example_from_path(path);
+    util::run_hashed_example_from_path(path);
 }
@@ -0,0 +1,243 @@
+use crate::merkle_tree_error::MerkleTreeError;
+
+/// The `AbiType` enum represents the Solidity types that can be used to encode the leaves of a `SortedPairTree`.
+///
+/// Only static types are supported, so every value is encoded into exactly one 32 byte word, as done by `abi.encode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbiType {
+    Address,
+    Bool,
+    Uint256,
+    Bytes32,
+}
+
+impl AbiType {
+    /// Parses a Solidity type name, like `address` or `uint256`.
+    pub fn parse(name: &str) -> Result<AbiType, MerkleTreeError> {
+        match name.trim() {
+            "address" => Ok(AbiType::Address),
+            "bool" => Ok(AbiType::Bool),
+            "uint256" | "uint" => Ok(AbiType::Uint256),
+            "bytes32" => Ok(AbiType::Bytes32),
+            other => Err(MerkleTreeError::InvalidValue(format!(
+                "Unsupported type: {}",
+                other
+            ))),
+        }
+    }
+
+    /// Returns the canonical Solidity name of the type.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AbiType::Address => "address",
+            AbiType::Bool => "bool",
+            AbiType::Uint256 => "uint256",
+            AbiType::Bytes32 => "bytes32",
+        }
+    }
+
+    /// Encodes a value of this type into a 32 byte word.
+    ///
+    /// # Parameters
+    /// - `value`: The value as text. Addresses and `bytes32` are `0x` prefixed hex, integers are decimal or `0x` prefixed hex and booleans are `true` or `false`.
+    pub fn encode(&self, value: &str) -> Result<[u8; 32], MerkleTreeError> {
+        let value = value.trim();
+        let mut word = [0u8; 32];
+
+        match self {
+            AbiType::Address => {
+                let bytes = AbiType::decode_hex(value, 20)?;
+                word[12..].copy_from_slice(&bytes);
+            }
+            AbiType::Bool => match value {
+                "true" => word[31] = 1,
+                "false" => (),
+                _ => {
+                    return Err(MerkleTreeError::InvalidValue(format!(
+                        "Invalid bool: {}",
+                        value
+                    )))
+                }
+            },
+            AbiType::Uint256 => word = AbiType::parse_uint256(value)?,
+            AbiType::Bytes32 => {
+                let bytes = AbiType::decode_hex(value, 32)?;
+                word.copy_from_slice(&bytes);
+            }
+        }
+
+        Ok(word)
+    }
+
+    /// Returns the canonical text of a value, so that equal values are always written the same way.
+    /// Hex values are lowercased and integers are written in decimal.
+    pub fn canonicalize(&self, value: &str) -> Result<String, MerkleTreeError> {
+        let word = self.encode(value)?;
+
+        let canonical = match self {
+            AbiType::Address => format!("0x{}", AbiType::to_hex(&word[12..])),
+            AbiType::Bool => (word[31] == 1).to_string(),
+            AbiType::Uint256 => AbiType::uint256_to_decimal(word),
+            AbiType::Bytes32 => format!("0x{}", AbiType::to_hex(&word)),
+        };
+
+        Ok(canonical)
+    }
+
+    /// Encodes a list of values as `abi.encode` does, one 32 byte word per value.
+    ///
+    /// # Parameters
+    /// - `types`: The types of the values, in order
+    /// - `values`: The values to encode
+    pub fn encode_all(types: &[AbiType], values: &[String]) -> Result<Vec<u8>, MerkleTreeError> {
+        if types.len() != values.len() {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "Expected {} values but got {}",
+                types.len(),
+                values.len()
+            )));
+        }
+
+        let mut encoded = Vec::with_capacity(32 * types.len());
+        for (abi_type, value) in types.iter().zip(values) {
+            encoded.extend_from_slice(&abi_type.encode(value)?);
+        }
+
+        Ok(encoded)
+    }
+
+    /// Decodes a `0x` prefixed hex string that must contain exactly `len` bytes.
+    fn decode_hex(value: &str, len: usize) -> Result<Vec<u8>, MerkleTreeError> {
+        let digits = value.strip_prefix("0x").ok_or_else(|| {
+            MerkleTreeError::InvalidValue(format!("Expected a 0x prefixed value: {}", value))
+        })?;
+
+        if !digits.is_ascii() {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "Invalid hex value: {}",
+                value
+            )));
+        }
+        if digits.len() != 2 * len {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "Expected {} bytes: {}",
+                len, value
+            )));
+        }
+
+        (0..len)
+            .map(|i| {
+                u8::from_str_radix(&digits[2 * i..2 * i + 2], 16).map_err(|_| {
+                    MerkleTreeError::InvalidValue(format!("Invalid hex value: {}", value))
+                })
+            })
+            .collect()
+    }
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// Writes a big endian word as a decimal number.
+    fn uint256_to_decimal(mut word: [u8; 32]) -> String {
+        let mut digits = vec![];
+
+        while word.iter().any(|byte| *byte != 0) {
+            let mut remainder = 0u32;
+            for byte in word.iter_mut() {
+                let acc = (remainder << 8) | *byte as u32;
+                *byte = (acc / 10) as u8;
+                remainder = acc % 10;
+            }
+            digits.push(char::from(b'0' + remainder as u8));
+        }
+
+        if digits.is_empty() {
+            return "0".to_string();
+        }
+        digits.iter().rev().collect()
+    }
+
+    /// Parses an unsigned integer that fits in 256 bits into a big endian word.
+    fn parse_uint256(value: &str) -> Result<[u8; 32], MerkleTreeError> {
+        let invalid = || MerkleTreeError::InvalidValue(format!("Invalid uint256: {}", value));
+
+        let (digits, radix) = match value.strip_prefix("0x") {
+            Some(digits) => (digits, 16),
+            None => (value, 10),
+        };
+
+        if digits.is_empty() {
+            return Err(invalid());
+        }
+
+        let mut word = [0u8; 32];
+        for c in digits.chars() {
+            let mut carry = c.to_digit(radix).ok_or_else(invalid)?;
+            for byte in word.iter_mut().rev() {
+                let acc = *byte as u32 * radix + carry;
+                *byte = acc as u8;
+                carry = acc >> 8;
+            }
+            if carry != 0 {
+                return Err(invalid());
+            }
+        }
+
+        Ok(word)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::abi_type::AbiType;
+
+    #[test]
+    fn encode_uint256() {
+        let word = AbiType::Uint256.encode("5000000000000000000").unwrap();
+        assert_eq!(&word[24..], &5000000000000000000u64.to_be_bytes());
+        assert!(word[..24].iter().all(|b| *b == 0));
+
+        let max = format!("0x{}", "f".repeat(64));
+        assert_eq!(AbiType::Uint256.encode(&max).unwrap(), [0xff; 32]);
+
+        let overflow = format!("0x1{}", "0".repeat(64));
+        assert!(AbiType::Uint256.encode(&overflow).is_err());
+        assert!(AbiType::Uint256.encode("-1").is_err());
+    }
+
+    #[test]
+    fn canonicalize_values() {
+        assert_eq!(AbiType::Uint256.canonicalize("0x0a").unwrap(), "10");
+        assert_eq!(AbiType::Uint256.canonicalize("000").unwrap(), "0");
+        assert_eq!(
+            AbiType::Uint256
+                .canonicalize(&u128::MAX.to_string())
+                .unwrap(),
+            u128::MAX.to_string()
+        );
+        assert_eq!(
+            AbiType::Address
+                .canonicalize("0xABCDEFabcdef0000000000000000000000000000")
+                .unwrap(),
+            "0xabcdefabcdef0000000000000000000000000000"
+        );
+    }
+
+    #[test]
+    fn encode_address_is_left_padded() {
+        let word = AbiType::Address
+            .encode("0x1111111111111111111111111111111111111111")
+            .unwrap();
+        assert_eq!(&word[..12], &[0; 12]);
+        assert_eq!(&word[12..], &[0x11; 20]);
+
+        assert!(AbiType::Address.encode("0x1111").is_err());
+        assert!(AbiType::Address
+            .encode(&format!("0x1{}1", "é".repeat(19)))
+            .is_err());
+        assert!(AbiType::Bytes32
+            .encode(&format!("0x1{}1", "ü".repeat(31)))
+            .is_err());
+    }
+}
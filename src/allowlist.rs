@@ -0,0 +1,253 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+use crate::abi_type::AbiType;
+use crate::merkle_hash::MerkleHash;
+use crate::merkle_tree_error::MerkleTreeError;
+use crate::sorted_pair_tree::SortedPairTree;
+
+/// An allowlist, or airdrop, built from a CSV file as a `SortedPairTree`.
+///
+/// The first line of the CSV is a header with the names of the columns, and every other line is an entry whose columns are encoded with the given Solidity types. The first column (usually the address) identifies the entry and must be unique.
+///
+/// # Methods
+/// - `from_csv`: Creates a new allowlist from the contents of a CSV file.
+/// - `from_csv_file`: Creates a new allowlist from a CSV file.
+/// - `root`: Returns the root of the tree.
+/// - `bundle`: Returns the root and the proof of every entry as JSON.
+#[derive(Debug)]
+pub struct Allowlist {
+    columns: Vec<String>,
+    tree: SortedPairTree,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AllowlistBundle<'a> {
+    root: MerkleHash,
+    leaf_encoding: Vec<&'static str>,
+    columns: &'a [String],
+    count: usize,
+    claims: BTreeMap<&'a String, Claim<'a>>,
+}
+
+#[derive(Serialize)]
+struct Claim<'a> {
+    index: usize,
+    value: &'a [String],
+    leaf: MerkleHash,
+    proof: Vec<MerkleHash>,
+}
+
+impl Allowlist {
+    /// Creates a new allowlist from the contents of a CSV file.
+    ///
+    /// # Parameters
+    /// - `csv`: The contents of the file, starting with the header
+    /// - `types`: The Solidity types of the columns
+    ///
+    /// # Returns
+    /// A Result that contains the allowlist, or an error with the line number of the first malformed or duplicated entry.
+    pub fn from_csv(csv: &str, types: &[AbiType]) -> Result<Allowlist, MerkleTreeError> {
+        let mut lines = csv
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+
+        let columns = match lines.next() {
+            Some((_, header)) => Allowlist::split_row(header),
+            None => {
+                return Err(MerkleTreeError::FailedToProcessFile(
+                    "The CSV file is empty".to_string(),
+                ))
+            }
+        };
+
+        if columns.len() != types.len() {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "The header has {} columns but {} types were given",
+                columns.len(),
+                types.len()
+            )));
+        }
+
+        let mut values = vec![];
+        let mut seen: HashMap<String, usize> = HashMap::new();
+
+        for (line_number, line) in lines {
+            let row = Allowlist::split_row(line);
+            if row.len() != types.len() {
+                return Err(MerkleTreeError::InvalidValue(format!(
+                    "Line {}: expected {} columns but found {}",
+                    line_number,
+                    types.len(),
+                    row.len()
+                )));
+            }
+
+            let value = row
+                .iter()
+                .zip(types)
+                .map(|(field, abi_type)| abi_type.canonicalize(field))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| {
+                    MerkleTreeError::InvalidValue(format!("Line {}: {:?}", line_number, e))
+                })?;
+
+            if let Some(first_line) = seen.insert(value[0].clone(), line_number) {
+                return Err(MerkleTreeError::InvalidValue(format!(
+                    "Line {}: duplicated entry {}, already found on line {}",
+                    line_number, value[0], first_line
+                )));
+            }
+
+            values.push(value);
+        }
+
+        if values.is_empty() {
+            return Err(MerkleTreeError::FailedToProcessFile(
+                "The CSV file has no entries".to_string(),
+            ));
+        }
+
+        let tree = SortedPairTree::of(values, types.to_vec())?;
+        Ok(Allowlist { columns, tree })
+    }
+
+    /// Creates a new allowlist from a CSV file.
+    ///
+    /// # Parameters
+    /// - `path`: The path of the CSV file
+    /// - `types`: The Solidity types of the columns
+    pub fn from_csv_file(path: &str, types: &[AbiType]) -> Result<Allowlist, MerkleTreeError> {
+        let csv = std::fs::read_to_string(path)
+            .map_err(|_| MerkleTreeError::FailedToProcessFile("Failed to read file".to_string()))?;
+        Allowlist::from_csv(&csv, types)
+    }
+
+    /// Returns the root of the tree.
+    pub fn root(&self) -> MerkleHash {
+        self.tree.root()
+    }
+
+    /// Returns the number of entries in the allowlist.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns true if the allowlist has no entries. Allowlists are never empty, it is provided for completeness.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Returns the tree of the allowlist.
+    pub fn tree(&self) -> &SortedPairTree {
+        &self.tree
+    }
+
+    /// Consumes the allowlist, returning its tree.
+    pub fn into_tree(self) -> SortedPairTree {
+        self.tree
+    }
+
+    /// Returns the root and, for every entry, its values, leaf and proof as JSON. Entries are keyed by their first column.
+    pub fn bundle(&self) -> Result<String, MerkleTreeError> {
+        let mut claims = BTreeMap::new();
+
+        for index in 0..self.tree.len() {
+            let value = match self.tree.value(index) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            let claim = Claim {
+                index,
+                value,
+                leaf: self.tree.leaf(index)?,
+                proof: self.tree.proof(index)?,
+            };
+            claims.insert(&value[0], claim);
+        }
+
+        let bundle = AllowlistBundle {
+            root: self.tree.root(),
+            leaf_encoding: self.tree.leaf_encoding().iter().map(|t| t.name()).collect(),
+            columns: &self.columns,
+            count: self.tree.len(),
+            claims,
+        };
+
+        serde_json::to_string_pretty(&bundle)
+            .map_err(|e| MerkleTreeError::FailedToBuild(format!("Failed to export bundle: {}", e)))
+    }
+
+    /// Splits a CSV row in its fields, removing the quotes around them.
+    fn split_row(line: &str) -> Vec<String> {
+        line.split(',')
+            .map(|field| field.trim().trim_matches('"').trim().to_string())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::abi_type::AbiType;
+    use crate::allowlist::Allowlist;
+    use crate::sorted_pair_tree::SortedPairTree;
+
+    const TYPES: [AbiType; 2] = [AbiType::Address, AbiType::Uint256];
+
+    #[test]
+    fn build_from_csv() {
+        let csv = "address,amount\n\
+                   0x1111111111111111111111111111111111111111,5000000000000000000\n\
+                   \n\
+                   0x2222222222222222222222222222222222222222,\"2500000000000000000\"\n";
+
+        let allowlist = Allowlist::from_csv(csv, &TYPES).unwrap();
+
+        assert_eq!(allowlist.len(), 2);
+        assert_eq!(
+            allowlist.root(),
+            "0xd4dee0beab2d53f2cc83e567171bd2820e49898130a22622b10ead383e90bd77"
+        );
+
+        let bundle: serde_json::Value = serde_json::from_str(&allowlist.bundle().unwrap()).unwrap();
+        let claim = &bundle["claims"]["0x2222222222222222222222222222222222222222"];
+        let proof: Vec<String> = serde_json::from_value(claim["proof"].clone()).unwrap();
+
+        assert!(SortedPairTree::verify(
+            &allowlist.root(),
+            &claim["leaf"].as_str().unwrap().to_string(),
+            &proof
+        ));
+    }
+
+    #[test]
+    fn duplicated_addresses_are_rejected() {
+        let csv = "address,amount\n\
+                   0x1111111111111111111111111111111111111111,1\n\
+                   0x2222222222222222222222222222222222222222,2\n\
+                   0x1111111111111111111111111111111111111111,3\n";
+
+        let err = format!("{:?}", Allowlist::from_csv(csv, &TYPES).unwrap_err());
+
+        assert!(err.contains("Line 4"));
+        assert!(err.contains("line 2"));
+    }
+
+    #[test]
+    fn malformed_rows_report_their_line() {
+        let csv = "address,amount\n\
+                   0x1111111111111111111111111111111111111111,1\n\
+                   0x2222,2\n";
+        let err = format!("{:?}", Allowlist::from_csv(csv, &TYPES).unwrap_err());
+        assert!(err.contains("Line 3"));
+
+        let csv = "address,amount\n0x1111111111111111111111111111111111111111\n";
+        let err = format!("{:?}", Allowlist::from_csv(csv, &TYPES).unwrap_err());
+        assert!(err.contains("Line 2"));
+    }
+}
@@ -0,0 +1,265 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use sha3::{Digest, Sha3_256};
+
+use crate::merkle_hash::{MerkleDigest, MerkleHash};
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+use crate::proof_of_inclusion::ProofOfInclusion;
+
+/// Smallest chunk size accepted by `parse_chunk_size`.
+pub const MIN_CHUNK_SIZE: usize = 16 * 1024;
+
+/// Biggest chunk size accepted by `parse_chunk_size`.
+pub const MAX_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// The `ChunkedFile` struct is a Merkle Tree whose leaves are the SHA3-256 hashes of the fixed-size chunks of a file, so every chunk can be verified on its own against the root.
+///
+/// Every chunk has `chunk_size` bytes but the last one, which can be shorter. An empty file has a single empty chunk.
+#[derive(Debug)]
+pub struct ChunkedFile {
+    tree: MerkleTree,
+    chunk_size: usize,
+    file_len: u64,
+}
+
+impl ChunkedFile {
+    /// Splits the contents of a reader in chunks and builds the tree of their hashes.
+    ///
+    /// # Parameters
+    /// - `reader`: The contents to hash
+    /// - `chunk_size`: The size of every chunk, in bytes
+    pub fn from_reader(
+        mut reader: impl Read,
+        chunk_size: usize,
+    ) -> Result<ChunkedFile, MerkleTreeError> {
+        if chunk_size == 0 {
+            return Err(MerkleTreeError::InvalidValue(
+                "The chunk size must be greater than 0".to_string(),
+            ));
+        }
+
+        let mut leaves = vec![];
+        let mut chunk = vec![0u8; chunk_size];
+        let mut file_len = 0;
+
+        loop {
+            let read = ChunkedFile::read_full(&mut reader, &mut chunk)?;
+            if read == 0 && !leaves.is_empty() {
+                break;
+            }
+            leaves.push(ChunkedFile::hash_chunk(&chunk[..read]));
+            file_len += read as u64;
+            if read < chunk_size {
+                break;
+            }
+        }
+
+        Ok(ChunkedFile {
+            tree: MerkleTree::new_from_digests(leaves)?,
+            chunk_size,
+            file_len,
+        })
+    }
+
+    /// Builds the tree of the chunks of a file.
+    pub fn from_file(
+        path: impl AsRef<Path>,
+        chunk_size: usize,
+    ) -> Result<ChunkedFile, MerkleTreeError> {
+        let file =
+            File::open(path).map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))?;
+        ChunkedFile::from_reader(BufReader::new(file), chunk_size)
+    }
+
+    /// Reads until the buffer is full or the reader ends, returning the number of bytes read.
+    fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> Result<usize, MerkleTreeError> {
+        let mut read = 0;
+        while read < buffer.len() {
+            match reader.read(&mut buffer[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(MerkleTreeError::FailedToProcessFile(e.to_string())),
+            }
+        }
+        Ok(read)
+    }
+
+    fn hash_chunk(chunk: &[u8]) -> MerkleDigest {
+        Sha3_256::digest(chunk).into()
+    }
+
+    /// Parses a chunk size given in bytes or with a `K`, `KiB`, `M` or `MiB` suffix, e.g. `16KiB` or `4M`, which must be between `MIN_CHUNK_SIZE` and `MAX_CHUNK_SIZE`.
+    pub fn parse_chunk_size(size: &str) -> Result<usize, MerkleTreeError> {
+        let size = size.trim();
+        let digits = size
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(size.len());
+        let multiplier = match size[digits..].trim().to_lowercase().as_str() {
+            "" | "b" => 1,
+            "k" | "kib" | "kb" => 1024,
+            "m" | "mib" | "mb" => 1024 * 1024,
+            _ => {
+                return Err(MerkleTreeError::InvalidValue(format!(
+                    "Invalid chunk size: {}",
+                    size
+                )))
+            }
+        };
+
+        let value = size[..digits]
+            .parse::<usize>()
+            .ok()
+            .and_then(|value| value.checked_mul(multiplier));
+        match value {
+            Some(value) if (MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&value) => Ok(value),
+            Some(_) => Err(MerkleTreeError::InvalidValue(format!(
+                "The chunk size must be between 16 KiB and 4 MiB: {}",
+                size
+            ))),
+            None => Err(MerkleTreeError::InvalidValue(format!(
+                "Invalid chunk size: {}",
+                size
+            ))),
+        }
+    }
+
+    /// Returns the root of the tree.
+    pub fn root(&self) -> Result<MerkleHash, MerkleTreeError> {
+        self.tree.root()
+    }
+
+    /// Returns the number of chunks of the file.
+    pub fn chunk_count(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns the size of the chunks, in bytes.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Returns the size of the file, in bytes.
+    pub fn file_len(&self) -> u64 {
+        self.file_len
+    }
+
+    /// Returns the tree of the chunks.
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    /// Returns the proof of inclusion of the chunk at the given index.
+    pub fn proof(&self, index: usize) -> Result<ProofOfInclusion, MerkleTreeError> {
+        let leaf = self.tree.node(0, index).ok_or_else(|| {
+            MerkleTreeError::InvalidValue(format!("There is no chunk at index {}", index))
+        })?;
+        self.tree.proof_of_inclusion_with_index(&leaf, index as u32)
+    }
+
+    /// Reads the chunk at the given index from a file.
+    ///
+    /// # Parameters
+    /// - `path`: The path of the file
+    /// - `chunk_size`: The size of the chunks the file was split in
+    /// - `index`: The index of the chunk
+    pub fn read_chunk(
+        path: impl AsRef<Path>,
+        chunk_size: usize,
+        index: usize,
+    ) -> Result<Vec<u8>, MerkleTreeError> {
+        let mut file =
+            File::open(path).map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))?;
+        file.seek(SeekFrom::Start((index * chunk_size) as u64))
+            .map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))?;
+
+        let mut chunk = vec![0u8; chunk_size];
+        let read = ChunkedFile::read_full(&mut file, &mut chunk)?;
+        chunk.truncate(read);
+        Ok(chunk)
+    }
+
+    /// Verifies a chunk against a root with its proof, without needing the tree.
+    ///
+    /// # Parameters
+    /// - `root`: The root of the tree of the file
+    /// - `chunk`: The contents of the chunk
+    /// - `proof`: The proof of inclusion of the chunk
+    pub fn verify_chunk(root: &MerkleHash, chunk: &[u8], proof: &ProofOfInclusion) -> bool {
+        let leaf = MerkleTree::digest_to_hex(&ChunkedFile::hash_chunk(chunk));
+        *proof.leaf() == leaf && proof.verify(root)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::chunked_file::ChunkedFile;
+    use crate::merkle_tree::MerkleTree;
+
+    #[test]
+    fn chunks_are_hashed_as_leaves() {
+        let data: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+        let file = ChunkedFile::from_reader(data.as_slice(), 1024).unwrap();
+
+        let expected = MerkleTree::new_from_hashes(
+            data.chunks(1024)
+                .map(|chunk| MerkleTree::get_hash_of(&chunk))
+                .collect(),
+        )
+        .unwrap();
+        assert_eq!(file.chunk_count(), 3);
+        assert_eq!(file.file_len(), 2500);
+        assert_eq!(file.root().unwrap(), expected.root().unwrap());
+
+        let exact = ChunkedFile::from_reader(&data[..2048], 1024).unwrap();
+        assert_eq!(exact.chunk_count(), 2);
+        let empty = ChunkedFile::from_reader(&[][..], 1024).unwrap();
+        assert_eq!(empty.chunk_count(), 1);
+    }
+
+    #[test]
+    fn chunks_read_from_disk_are_verified() {
+        let path = std::env::temp_dir().join(format!("merkle-chunks-{}", std::process::id()));
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i * 7 % 256) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let file = ChunkedFile::from_file(&path, 4096).unwrap();
+        let root = file.root().unwrap();
+        for index in 0..file.chunk_count() {
+            let chunk = ChunkedFile::read_chunk(&path, 4096, index).unwrap();
+            assert!(ChunkedFile::verify_chunk(
+                &root,
+                &chunk,
+                &file.proof(index).unwrap()
+            ));
+        }
+
+        let mut chunk = ChunkedFile::read_chunk(&path, 4096, 1).unwrap();
+        chunk[10] ^= 1;
+        assert!(!ChunkedFile::verify_chunk(
+            &root,
+            &chunk,
+            &file.proof(1).unwrap()
+        ));
+        assert!(file.proof(3).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn chunk_sizes_are_parsed() {
+        assert_eq!(ChunkedFile::parse_chunk_size("16KiB").unwrap(), 16 * 1024);
+        assert_eq!(ChunkedFile::parse_chunk_size("4M").unwrap(), 4 << 20);
+        assert_eq!(ChunkedFile::parse_chunk_size("65536").unwrap(), 65536);
+        assert!(ChunkedFile::parse_chunk_size("0").is_err());
+        assert!(ChunkedFile::parse_chunk_size("1b").is_err());
+        assert!(ChunkedFile::parse_chunk_size("1000").is_err());
+        assert!(ChunkedFile::parse_chunk_size("100000m").is_err());
+        assert!(ChunkedFile::parse_chunk_size("99999999999999999999k").is_err());
+        assert!(ChunkedFile::parse_chunk_size("18014398509481984m").is_err());
+        assert!(ChunkedFile::parse_chunk_size("12GiB").is_err());
+    }
+}
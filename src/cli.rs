@@ -1,7 +1,22 @@
 use clap::{Parser, Subcommand};
 
+use crate::abi_type::AbiType;
+use crate::allowlist::Allowlist;
+use crate::chunked_file::ChunkedFile;
+use crate::dir_tree::DirTree;
+use crate::file_store::FileStore;
+use crate::manifest::{Manifest, ManifestEntry};
+#[cfg(feature = "server")]
+use crate::memory_store::MemoryStore;
 use crate::merkle_tree::MerkleTree;
 use crate::merkle_tree_error::MerkleTreeError;
+use crate::node_store::NodeStore;
+#[cfg(feature = "server")]
+use crate::server::TreeServer;
+use crate::signed_tree_head::{self, SignedTreeHead, TreeHead};
+use crate::sorted_pair_tree::SortedPairTree;
+use crate::sync;
+use std::ops::Range;
 use std::vec;
 
 #[derive(Parser, Debug)]
@@ -36,13 +51,18 @@ enum Commands {
         index: Option<u32>,
     },
 
-    /// Shows the proof of inclusion for an element.
+    /// Shows the proof of inclusion for an element, or for a range of leaves with `--range`.
     Proof {
         /// The element to get proof of inclusion for
-        elem: String,
+        #[arg(required_unless_present = "range")]
+        elem: Option<String>,
 
         /// Optionally provide the index for proof of inclusion
         index: Option<u32>,
+
+        /// Proves the leaves from `start` to `end`, excluded, with a single proof
+        #[arg(long, value_name = "START..END", conflicts_with_all = ["elem", "index"])]
+        range: Option<String>,
     },
 
     /// Adds an element to the Merkle Tree.
@@ -56,24 +76,225 @@ enum Commands {
         hash: bool,
     },
 
+    /// Creates a tree compatible with OpenZeppelin's `StandardMerkleTree` from a file with one value per line.
+    /// The fields of every value are separated by commas and encoded with the given Solidity types.
+    SortedCreate {
+        /// Path to the file containing the values
+        path: String,
+
+        /// Solidity types of the fields, separated by commas (e.g. `address,uint256`)
+        #[arg(long, value_delimiter = ',', required = true)]
+        types: Vec<String>,
+    },
+
+    /// Shows the `bytes32[]` proof for the value at the given index of the sorted pair tree.
+    SortedProof {
+        /// The index of the value
+        index: usize,
+    },
+
+    /// Exports the sorted pair tree as an OpenZeppelin `standard-v1` dump.
+    SortedExport {
+        /// Path of the dump file
+        path: String,
+
+        /// Optionally write the root and the proofs of every value to this file
+        #[arg(long)]
+        proofs: Option<String>,
+    },
+
+    /// Creates an allowlist from a CSV file, whose first line is a header, and writes its root and the proof of every entry to a JSON file.
+    /// The allowlist is also loaded as the current sorted pair tree.
+    Allowlist {
+        /// Path to the CSV file
+        path: String,
+
+        /// Path of the JSON file to write
+        output: String,
+
+        /// Solidity types of the columns, separated by commas
+        #[arg(long, value_delimiter = ',', default_value = "address,uint256")]
+        types: Vec<String>,
+    },
+
+    /// Splits a file in fixed-size chunks and builds the tree of their hashes, showing its root and number of chunks.
+    HashFile {
+        /// Path to the file
+        path: String,
+
+        /// Size of the chunks, in bytes or with a `KiB` or `MiB` suffix, from 16 KiB to 4 MiB
+        #[arg(long, default_value = "16KiB")]
+        chunk_size: String,
+    },
+
+    /// Reads a chunk from disk and verifies it against the root of the last hashed file with its proof.
+    VerifyChunk {
+        /// The index of the chunk
+        index: usize,
+
+        /// Optionally read the chunk from this file instead of the hashed one
+        #[arg(long)]
+        path: Option<String>,
+    },
+
+    /// Hashes every file of a directory into a tree and shows its root.
+    /// Optionally saves its manifest, or lists the files that changed since a saved one.
+    HashDir {
+        /// Path to the directory
+        path: String,
+
+        /// Patterns of the files and directories to skip, separated by commas (e.g. `target,*.log`)
+        #[arg(long, value_delimiter = ',')]
+        ignore: Vec<String>,
+
+        /// Optionally write the manifest of the directory to this file
+        #[arg(long)]
+        save: Option<String>,
+
+        /// Optionally list the files added, removed and modified since the manifest in this file
+        #[arg(long)]
+        compare: Option<String>,
+    },
+
+    /// Creates or verifies a manifest with the root and the labeled leaves of a directory or of a file of records.
+    Manifest {
+        #[command(subcommand)]
+        action: ManifestAction,
+    },
+
+    /// Lists the ranges of leaf indices that differ between two trees.
+    /// Each tree can be a directory with a stored tree, a manifest (`.json`) or a file with elements.
+    Diff {
+        /// The first tree
+        a: String,
+
+        /// The second tree
+        b: String,
+
+        /// Hash the elements of element files before adding them to the tree
+        #[arg(long)]
+        hash: bool,
+    },
+
+    /// Synchronizes the current tree with another replica over TCP.
+    /// `--listen` serves the current tree to one replica, `--connect` makes the current tree equal to the one served at the given address.
+    Sync {
+        /// Address to listen on (e.g. `127.0.0.1:7000`)
+        #[arg(long, conflicts_with = "connect", required_unless_present = "connect")]
+        listen: Option<String>,
+
+        /// Address of the replica to pull from
+        #[arg(long)]
+        connect: Option<String>,
+    },
+
+    /// Names the current state of the tree so it can be rewound to, or lists the checkpoints if no name is given. Checkpoints are kept in memory and lost when the tree is loaded again.
+    Checkpoint {
+        /// The name of the checkpoint
+        name: Option<String>,
+    },
+
+    /// Restores the leaves and the root the tree had at a checkpoint, dropping the checkpoints created after it.
+    Rewind {
+        /// The name of the checkpoint
+        name: String,
+    },
+
+    /// Signs the head of the current tree (size, root, timestamp and hash algorithm) with an Ed25519 key and writes it to a file.
+    Sign {
+        /// Path of the secret key, in hex
+        key: String,
+
+        /// Path of the signed tree head to write
+        output: String,
+
+        /// Generate a new key at the key path, and its public key with a `.pub` extension, before signing
+        #[arg(long)]
+        generate_key: bool,
+    },
+
+    /// Verifies the signature of a signed tree head with the public key of the log, and optionally that an element of the current tree is included under its root.
+    VerifySth {
+        /// Path of the signed tree head
+        sth: String,
+
+        /// Path of the public key, in hex
+        public_key: String,
+
+        /// Hash of an element of the current tree to check against the signed root
+        #[arg(long)]
+        elem: Option<String>,
+    },
+
+    /// Serves the current tree through a JSON REST API over HTTP until Enter is pressed, keeping the leaves appended to it.
+    #[cfg(feature = "server")]
+    Serve {
+        /// Loopback address to listen on
+        #[arg(default_value = "127.0.0.1:8080")]
+        address: String,
+
+        /// Number of threads answering requests
+        #[arg(long, default_value_t = 4)]
+        workers: usize,
+    },
+
     /// Exit the CLI
     Exit,
 }
 
+#[derive(Subcommand, Debug)]
+enum ManifestAction {
+    /// Writes the manifest of a directory, with one entry per file, or of a file, with one entry per line labeled with its first comma-separated field.
+    Create {
+        /// Path to the directory or file
+        path: String,
+
+        /// Path of the manifest to write
+        output: String,
+
+        /// Patterns of the files and directories to skip, separated by commas
+        #[arg(long, value_delimiter = ',')]
+        ignore: Vec<String>,
+    },
+
+    /// Checks a directory or file against a manifest, listing the entries that no longer match.
+    Verify {
+        /// Path of the manifest
+        manifest: String,
+
+        /// Path to the directory or file
+        path: String,
+
+        /// Patterns of the files and directories to skip, separated by commas
+        #[arg(long, value_delimiter = ',')]
+        ignore: Vec<String>,
+    },
+}
+
 /// The `CLI` struct is used to manage the command line interface of the Merkle Tree.
 pub struct CLI {
     tree: MerkleTree,
+    sorted_tree: Option<SortedPairTree>,
+    chunked_file: Option<(String, ChunkedFile)>,
 }
 
 impl CLI {
     /// Creates a new `CLI` struct.
     pub fn new() -> Result<Self, MerkleTreeError> {
         let tree = MerkleTree::new_from_hashables(vec![""])?;
-        Ok(CLI { tree })
+        Ok(CLI {
+            tree,
+            sorted_tree: None,
+            chunked_file: None,
+        })
     }
 
     pub fn new_from_tree(tree: MerkleTree) -> Self {
-        CLI { tree }
+        CLI {
+            tree,
+            sorted_tree: None,
+            chunked_file: None,
+        }
     }
 
     /// Processes the input commands from the user and manages the CLI.
@@ -83,8 +304,49 @@ impl CLI {
                 Commands::Create { path, hash } => self.handle_create_tree(path, hash),
                 Commands::Show => self.tree.print(),
                 Commands::Verify { elem, index } => self.handle_verify_inclusion(&elem, index),
-                Commands::Proof { elem, index } => self.handle_proof_of_inclusion(&elem, index),
+                Commands::Proof {
+                    elem: Some(elem),
+                    index,
+                    ..
+                } => self.handle_proof_of_inclusion(&elem, index),
+                Commands::Proof {
+                    range: Some(range), ..
+                } => self.handle_range_proof(range),
+                Commands::Proof { .. } => (),
                 Commands::Add { elem, hash } => self.handle_add_element(elem, hash),
+                Commands::SortedCreate { path, types } => self.handle_sorted_create(path, types),
+                Commands::SortedProof { index } => self.handle_sorted_proof(index),
+                Commands::SortedExport { path, proofs } => self.handle_sorted_export(path, proofs),
+                Commands::Allowlist {
+                    path,
+                    output,
+                    types,
+                } => self.handle_allowlist(path, output, types),
+                Commands::HashFile { path, chunk_size } => self.handle_hash_file(path, chunk_size),
+                Commands::VerifyChunk { index, path } => self.handle_verify_chunk(index, path),
+                Commands::HashDir {
+                    path,
+                    ignore,
+                    save,
+                    compare,
+                } => self.handle_hash_dir(path, ignore, save, compare),
+                Commands::Manifest { action } => self.handle_manifest(action),
+                Commands::Diff { a, b, hash } => self.handle_diff(a, b, hash),
+                Commands::Sync { listen, connect } => self.handle_sync(listen, connect),
+                Commands::Checkpoint { name } => self.handle_checkpoint(name),
+                Commands::Rewind { name } => self.handle_rewind(&name),
+                Commands::Sign {
+                    key,
+                    output,
+                    generate_key,
+                } => self.handle_sign(key, output, generate_key),
+                Commands::VerifySth {
+                    sth,
+                    public_key,
+                    elem,
+                } => self.handle_verify_sth(sth, public_key, elem),
+                #[cfg(feature = "server")]
+                Commands::Serve { address, workers } => self.handle_serve(address, workers),
                 Commands::Exit => {
                     println!("Exiting...");
                     *running = false;
@@ -198,28 +460,589 @@ impl CLI {
         }
     }
 
+    /// Parses a range of leaves written as `start..end`.
+    fn parse_range(range: &str) -> Result<Range<usize>, MerkleTreeError> {
+        let invalid = || MerkleTreeError::InvalidValue(format!("Invalid range {:?}", range));
+        let (start, end) = range.split_once("..").ok_or_else(invalid)?;
+        let start = start.trim().parse().map_err(|_| invalid())?;
+        let end = end.trim().parse().map_err(|_| invalid())?;
+        Ok(start..end)
+    }
+
+    /// Handles the generation of the proof of a range of leaves, checking it against the root before printing it.
+    fn handle_range_proof(&mut self, range: String) {
+        let proof = CLI::parse_range(&range).and_then(|range| self.tree.range_proof(range));
+        match proof.and_then(|proof| {
+            proof.verify(self.tree.len(), &self.tree.root()?)?;
+            Ok(proof)
+        }) {
+            Ok(proof) => {
+                println!(
+                    "Proof for the leaves {}..{} of {}:",
+                    proof.start,
+                    proof.start + proof.leaves.len(),
+                    proof.size
+                );
+                println!("{}", serde_json::to_string(&proof).unwrap_or_default());
+            }
+            Err(e) => println!("Failed to prove the range {}. Error: {:?}", range, e),
+        }
+    }
+
     /// Handles the addition of an element to the Merkle Tree.
     /// The element can be added as a hash or as a string. The `--hash` flag is used to hash the element before adding it to the tree.
     fn handle_add_element(&mut self, elem: String, hash: bool) {
-        if hash {
-            match self.tree.add_data(&elem) {
-                Ok(_) => (),
-                Err(e) => {
-                    println!("{} is already in the tree! Error: {:?}", elem, e);
-                    return;
+        let added = match hash {
+            true => self.tree.add_data(&elem),
+            false => self.tree.add_hash(elem.clone()),
+        };
+
+        match added {
+            Ok(_) => println!("{:?} added to the tree.", elem),
+            Err(e @ MerkleTreeError::HashAlreadyExists(_)) => {
+                println!("{} is already in the tree! Error: {:?}", elem, e)
+            }
+            Err(e) => println!(
+                "Failed to add {:?} to the tree, without `--hash` it must be a hash of 64 lowercase hexadecimal characters. Error: {:?}",
+                elem, e
+            ),
+        }
+    }
+
+    /// Parses a list of Solidity types.
+    fn parse_types(types: &[String]) -> Result<Vec<AbiType>, MerkleTreeError> {
+        types.iter().map(|t| AbiType::parse(t)).collect()
+    }
+
+    /// Handles the creation of a tree compatible with OpenZeppelin's `StandardMerkleTree`.
+    fn handle_sorted_create(&mut self, path: String, types: Vec<String>) {
+        let types = match CLI::parse_types(&types) {
+            Ok(types) => types,
+            Err(e) => {
+                println!("Invalid types. Error: {:?}", e);
+                return;
+            }
+        };
+
+        let values = match CLI::process_file(&path) {
+            Ok(lines) => lines
+                .iter()
+                .map(|line| line.split(',').map(|f| f.trim().to_string()).collect())
+                .collect(),
+            Err(e) => {
+                println!("Failed to read file: {}. Error: {:?}", path, e);
+                return;
+            }
+        };
+
+        match SortedPairTree::of(values, types) {
+            Ok(tree) => {
+                println!(
+                    "Sorted pair tree created from file: {:?}, with root {}.",
+                    path,
+                    tree.root()
+                );
+                self.sorted_tree = Some(tree);
+            }
+            Err(e) => println!("Failed to build the sorted pair tree: {:?}", e),
+        }
+    }
+
+    /// Handles the generation of the proof of a value of the sorted pair tree.
+    fn handle_sorted_proof(&self, index: usize) {
+        let tree = match &self.sorted_tree {
+            Some(tree) => tree,
+            None => {
+                println!("There is no sorted pair tree, use 'tree sorted-create' first.");
+                return;
+            }
+        };
+
+        match (tree.value(index), tree.proof(index)) {
+            (Some(value), Ok(proof)) => {
+                println!("Proof for the value {:?}:", value);
+                println!("{}", serde_json::to_string(&proof).unwrap_or_default());
+            }
+            (_, Err(e)) => println!("There is no value at index {}. Error: {:?}", index, e),
+            (None, _) => println!("There is no value at index {}.", index),
+        }
+    }
+
+    /// Handles the export of the sorted pair tree, and optionally of the proofs of all its values.
+    fn handle_sorted_export(&self, path: String, proofs: Option<String>) {
+        let tree = match &self.sorted_tree {
+            Some(tree) => tree,
+            None => {
+                println!("There is no sorted pair tree, use 'tree sorted-create' first.");
+                return;
+            }
+        };
+
+        if let Err(e) = std::fs::write(&path, tree.dump()) {
+            println!("Failed to write file: {}. Error: {}", path, e);
+            return;
+        }
+        println!("Tree dump written to {:?}.", path);
+
+        if let Some(proofs_path) = proofs {
+            let result = tree.proofs_json().and_then(|json| {
+                std::fs::write(&proofs_path, json)
+                    .map_err(|e| MerkleTreeError::FailedToWriteFile(e.to_string()))
+            });
+            match result {
+                Ok(_) => println!("Proofs written to {:?}.", proofs_path),
+                Err(e) => println!("Failed to export proofs: {:?}", e),
+            }
+        }
+    }
+
+    /// Handles the creation of an allowlist from a CSV file and the export of its JSON bundle.
+    fn handle_allowlist(&mut self, path: String, output: String, types: Vec<String>) {
+        let types = match CLI::parse_types(&types) {
+            Ok(types) => types,
+            Err(e) => {
+                println!("Invalid types. Error: {:?}", e);
+                return;
+            }
+        };
+
+        let allowlist = match Allowlist::from_csv_file(&path, &types) {
+            Ok(allowlist) => allowlist,
+            Err(e) => {
+                println!(
+                    "Failed to build the allowlist from {:?}. Error: {:?}",
+                    path, e
+                );
+                return;
+            }
+        };
+
+        let result = allowlist.bundle().and_then(|bundle| {
+            std::fs::write(&output, bundle)
+                .map_err(|e| MerkleTreeError::FailedToWriteFile(e.to_string()))
+        });
+        if let Err(e) = result {
+            println!(
+                "Failed to write the allowlist to {:?}. Error: {:?}",
+                output, e
+            );
+            return;
+        }
+
+        println!(
+            "Allowlist with {} entries and root {} written to {:?}.",
+            allowlist.len(),
+            allowlist.root(),
+            output
+        );
+        self.sorted_tree = Some(allowlist.into_tree());
+    }
+
+    /// Handles the hashing of a file by chunks.
+    fn handle_hash_file(&mut self, path: String, chunk_size: String) {
+        let chunk_size = match ChunkedFile::parse_chunk_size(&chunk_size) {
+            Ok(chunk_size) => chunk_size,
+            Err(e) => {
+                println!("Invalid chunk size. Error: {:?}", e);
+                return;
+            }
+        };
+
+        let file = match ChunkedFile::from_file(&path, chunk_size) {
+            Ok(file) => file,
+            Err(e) => {
+                println!("Failed to hash file: {}. Error: {:?}", path, e);
+                return;
+            }
+        };
+
+        match file.root() {
+            Ok(root) => println!(
+                "File {:?} hashed in {} chunks of {} bytes, with root {}.",
+                path,
+                file.chunk_count(),
+                chunk_size,
+                root
+            ),
+            Err(e) => println!("Failed to hash file: {}. Error: {:?}", path, e),
+        }
+        self.chunked_file = Some((path, file));
+    }
+
+    /// Handles the verification of a chunk read from disk against the root of the last hashed file.
+    fn handle_verify_chunk(&self, index: usize, path: Option<String>) {
+        let (hashed_path, file) = match &self.chunked_file {
+            Some(chunked_file) => chunked_file,
+            None => {
+                println!("There is no hashed file, use 'tree hash-file' first.");
+                return;
+            }
+        };
+        let path = path.unwrap_or(hashed_path.clone());
+
+        let result = file.root().and_then(|root| {
+            let proof = file.proof(index)?;
+            let chunk = ChunkedFile::read_chunk(&path, file.chunk_size(), index)?;
+            Ok(ChunkedFile::verify_chunk(&root, &chunk, &proof))
+        });
+        match result {
+            Ok(true) => println!("Chunk {} of {:?} matches the root.", index, path),
+            Ok(false) => println!("Chunk {} of {:?} does NOT match the root.", index, path),
+            Err(e) => println!("Failed to verify chunk {}. Error: {:?}", index, e),
+        }
+    }
+
+    /// Handles the hashing of a directory, and the saving of its manifest or the comparison with a saved one.
+    fn handle_hash_dir(
+        &self,
+        path: String,
+        ignore: Vec<String>,
+        save: Option<String>,
+        compare: Option<String>,
+    ) {
+        let tree = match DirTree::from_dir(&path, &ignore) {
+            Ok(tree) => tree,
+            Err(e) => {
+                println!("Failed to hash directory: {}. Error: {:?}", path, e);
+                return;
+            }
+        };
+
+        match tree.root() {
+            Ok(root) => println!(
+                "Directory {:?} hashed with {} files, with root {}.",
+                path,
+                tree.files().len(),
+                root
+            ),
+            Err(e) => println!("Failed to hash directory: {}. Error: {:?}", path, e),
+        }
+
+        if let Some(manifest_path) = compare {
+            let old = std::fs::read_to_string(&manifest_path)
+                .map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))
+                .and_then(|json| Manifest::from_json(&json))
+                .and_then(|manifest| DirTree::from_manifest(&manifest));
+            match old {
+                Ok(old) => {
+                    let changes = match tree.changes_since(&old) {
+                        Ok(changes) => changes,
+                        Err(e) => {
+                            println!("Failed to compare with the manifest. Error: {:?}", e);
+                            return;
+                        }
+                    };
+                    if changes == Default::default() {
+                        println!("No changes since {:?}.", manifest_path);
+                    }
+                    for file in changes.added {
+                        println!("added: {}", file);
+                    }
+                    for file in changes.removed {
+                        println!("removed: {}", file);
+                    }
+                    for file in changes.modified {
+                        println!("modified: {}", file);
+                    }
                 }
+                Err(e) => println!("Failed to read manifest: {}. Error: {:?}", manifest_path, e),
+            }
+        }
+
+        if let Some(manifest_path) = save {
+            let result = tree
+                .manifest()
+                .and_then(|manifest| manifest.to_json())
+                .and_then(|json| {
+                    std::fs::write(&manifest_path, json)
+                        .map_err(|e| MerkleTreeError::FailedToWriteFile(e.to_string()))
+                });
+            match result {
+                Ok(_) => println!("Manifest written to {:?}.", manifest_path),
+                Err(e) => println!("Failed to write the manifest. Error: {:?}", e),
             }
+        }
+    }
+
+    /// Returns the manifest entries of a directory, one per file, or of a file of records, one per line.
+    fn manifest_entries(
+        path: &str,
+        ignore: &[String],
+    ) -> Result<Vec<ManifestEntry>, MerkleTreeError> {
+        if std::path::Path::new(path).is_dir() {
+            return DirTree::from_dir(path, ignore).map(|tree| tree.entries());
+        }
+        std::fs::read_to_string(path)
+            .map(|records| Manifest::record_entries(&records))
+            .map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))
+    }
+
+    /// Handles the creation and the verification of manifests.
+    fn handle_manifest(&self, action: ManifestAction) {
+        match action {
+            ManifestAction::Create {
+                path,
+                output,
+                ignore,
+            } => {
+                let result = CLI::manifest_entries(&path, &ignore)
+                    .and_then(Manifest::new)
+                    .and_then(|manifest| {
+                        let json = manifest.to_json()?;
+                        std::fs::write(&output, json)
+                            .map_err(|e| MerkleTreeError::FailedToWriteFile(e.to_string()))?;
+                        Ok(manifest)
+                    });
+                match result {
+                    Ok(manifest) => println!(
+                        "Manifest of {:?} with {} entries and root {} written to {:?}.",
+                        path, manifest.leaf_count, manifest.root, output
+                    ),
+                    Err(e) => println!("Failed to create the manifest. Error: {:?}", e),
+                }
+            }
+            ManifestAction::Verify {
+                manifest,
+                path,
+                ignore,
+            } => {
+                let result = std::fs::read_to_string(&manifest)
+                    .map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))
+                    .and_then(|json| Manifest::from_json(&json))
+                    .and_then(|manifest| {
+                        Ok(manifest.verify(&CLI::manifest_entries(&path, &ignore)?))
+                    });
+                match result {
+                    Ok(report) if report.is_clean() => {
+                        println!("Every entry of {:?} matches {:?}.", manifest, path)
+                    }
+                    Ok(report) => {
+                        for label in report.mismatched {
+                            println!("mismatched: {}", label);
+                        }
+                        for label in report.missing {
+                            println!("missing: {}", label);
+                        }
+                        for label in report.unexpected {
+                            println!("unexpected: {}", label);
+                        }
+                    }
+                    Err(e) => println!("Failed to verify the manifest. Error: {:?}", e),
+                }
+            }
+        }
+    }
+
+    /// Loads a tree from a directory kept by a `FileStore`, a manifest or a file with elements.
+    fn load_tree(
+        path: &str,
+        hash: bool,
+    ) -> Result<MerkleTree<Box<dyn NodeStore>>, MerkleTreeError> {
+        let store: Box<dyn NodeStore> = if std::path::Path::new(path).is_dir() {
+            Box::new(FileStore::open(path)?)
+        } else if path.ends_with(".json") {
+            let json = std::fs::read_to_string(path)
+                .map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))?;
+            Box::new(Manifest::from_json(&json)?.tree()?.into_store())
         } else {
-            match self.tree.add_hash(elem.clone()) {
-                Ok(_) => (),
+            let elements = CLI::process_file(path)?;
+            let tree = match hash {
+                true => MerkleTree::new_from_hashables(elements)?,
+                false => MerkleTree::new_from_hashes(elements)?,
+            };
+            Box::new(tree.into_store())
+        };
+        Ok(MerkleTree::from_store(store))
+    }
+
+    /// Handles the comparison of two trees.
+    fn handle_diff(&self, a: String, b: String, hash: bool) {
+        let result = CLI::load_tree(&a, hash)
+            .and_then(|tree_a| Ok((tree_a, CLI::load_tree(&b, hash)?)))
+            .and_then(|(tree_a, tree_b)| tree_a.diff(&tree_b));
+
+        match result {
+            Ok(ranges) if ranges.is_empty() => println!("The trees are equal."),
+            Ok(ranges) => {
+                for range in ranges {
+                    match range.len() {
+                        1 => println!("changed: {}", range.start),
+                        _ => println!("changed: {}..{}", range.start, range.end),
+                    }
+                }
+            }
+            Err(e) => println!("Failed to compare {:?} and {:?}. Error: {:?}", a, b, e),
+        }
+    }
+
+    /// Handles the synchronization of the current tree with another replica.
+    fn handle_sync(&mut self, listen: Option<String>, connect: Option<String>) {
+        if let Some(address) = listen {
+            let listener = match std::net::TcpListener::bind(&address) {
+                Ok(listener) => listener,
                 Err(e) => {
-                    println!("{} is already in the tree! Error: {:?}", elem, e);
+                    println!("Failed to listen on {}. Error: {}", address, e);
                     return;
                 }
+            };
+            println!("Waiting for a replica on {}...", address);
+            let result = listener
+                .accept()
+                .map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))
+                .and_then(|(mut stream, peer)| {
+                    sync::serve(&mut stream, &self.tree)?;
+                    Ok(peer)
+                });
+            match result {
+                Ok(peer) => println!("Tree served to {}.", peer),
+                Err(e) => println!("Failed to serve the tree. Error: {:?}", e),
+            }
+        } else if let Some(address) = connect {
+            let result = std::net::TcpStream::connect(&address)
+                .map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))
+                .and_then(|mut stream| sync::pull(&mut stream, &mut self.tree));
+            match result {
+                Ok(report) => println!(
+                    "Tree synchronized with {}: {} leaves, {} transferred in {} round trips.",
+                    address, report.len, report.transferred, report.round_trips
+                ),
+                Err(e) => println!("Failed to synchronize with {}. Error: {:?}", address, e),
+            }
+        }
+    }
+
+    /// Handles creating a checkpoint of the current tree, or listing them.
+    fn handle_checkpoint(&mut self, name: Option<String>) {
+        let name = match name {
+            Some(name) => name,
+            None => {
+                if self.tree.checkpoints().is_empty() {
+                    println!("There are no checkpoints.");
+                }
+                for checkpoint in self.tree.checkpoints() {
+                    println!(
+                        "{}: {} leaves, root {}",
+                        checkpoint.name(),
+                        checkpoint.size(),
+                        checkpoint.root().map_or("none", |root| root.as_str())
+                    );
+                }
+                return;
+            }
+        };
+
+        match self.tree.checkpoint(&name) {
+            Ok(checkpoint) => println!(
+                "Checkpoint {:?} created at {} leaves.",
+                name,
+                checkpoint.size()
+            ),
+            Err(e) => println!("Failed to create checkpoint {:?}. Error: {:?}", name, e),
+        }
+    }
+
+    /// Handles rewinding the current tree to a checkpoint.
+    fn handle_rewind(&mut self, name: &str) {
+        match self.tree.rewind(name) {
+            Ok(_) => println!(
+                "Tree rewound to checkpoint {:?}: {} leaves.",
+                name,
+                self.tree.len()
+            ),
+            Err(e) => println!("Failed to rewind to checkpoint {:?}. Error: {:?}", name, e),
+        }
+    }
+
+    /// Handles signing the head of the current tree.
+    fn handle_sign(&self, key_path: String, output: String, generate_key: bool) {
+        let key = match generate_key {
+            true => {
+                let key = signed_tree_head::generate_key();
+                signed_tree_head::write_keys(&key_path, &key).map(|_| key)
+            }
+            false => signed_tree_head::read_signing_key(&key_path),
+        };
+        let key = match key {
+            Ok(key) => key,
+            Err(e) => {
+                println!("Failed to get the key at {:?}. Error: {:?}", key_path, e);
+                return;
+            }
+        };
+
+        let result = TreeHead::new(&self.tree).and_then(|head| {
+            let sth = head.sign(&key);
+            sth.write(&output)?;
+            Ok(sth)
+        });
+        match result {
+            Ok(sth) => println!(
+                "Signed tree head of size {} and root {} written to {:?}.",
+                sth.head.tree_size, sth.head.root, output
+            ),
+            Err(e) => println!("Failed to sign the tree head. Error: {:?}", e),
+        }
+    }
+
+    /// Handles verifying a signed tree head, and the inclusion of an element under its root.
+    fn handle_verify_sth(&self, sth_path: String, public_key: String, elem: Option<String>) {
+        let result = SignedTreeHead::read(&sth_path).and_then(|sth| {
+            let key = signed_tree_head::read_verifying_key(&public_key)?;
+            sth.verify(&key)?;
+            let included = match &elem {
+                Some(elem) => Some(sth.verify_proof(&key, &self.tree.proof_of_inclusion(elem)?)?),
+                None => None,
+            };
+            Ok((sth, included))
+        });
+
+        match result {
+            Ok((sth, included)) => {
+                println!(
+                    "The tree head of size {} and root {} is signed by the key.",
+                    sth.head.tree_size, sth.head.root
+                );
+                match included {
+                    Some(true) => {
+                        println!("{:?} is included under the signed root.", elem.unwrap())
+                    }
+                    Some(false) => {
+                        println!("{:?} is NOT included under the signed root.", elem.unwrap())
+                    }
+                    None => (),
+                }
             }
+            Err(e) => println!("Failed to verify {:?}. Error: {:?}", sth_path, e),
         }
+    }
 
-        println!("{:?} added to the tree.", elem);
+    /// Handles serving the current tree over HTTP, taking it back when the server stops.
+    #[cfg(feature = "server")]
+    fn handle_serve(&mut self, address: String, workers: usize) {
+        let server = match TreeServer::bind(&address) {
+            Ok(server) => server,
+            Err(e) => {
+                println!("Failed to listen on {}. Error: {:?}", address, e);
+                return;
+            }
+        };
+        let tree = std::mem::replace(&mut self.tree, MerkleTree::from_store(MemoryStore::new()));
+        let handle = server.serve(tree, workers);
+
+        println!(
+            "Serving the tree on http://{}, press Enter to stop.",
+            handle.address()
+        );
+        let mut input = String::new();
+        let _ = std::io::stdin().read_line(&mut input);
+
+        let tree = handle.tree();
+        handle.shutdown();
+        match std::sync::Arc::try_unwrap(tree).map(|lock| lock.into_inner()) {
+            Ok(Ok(tree)) => self.tree = tree,
+            _ => println!("Failed to take back the served tree."),
+        }
+        println!("Server stopped.");
     }
 
     /// Reads the input from the user and returns a vector with the commands.
@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+
+use crate::direction::Direction;
+use crate::merkle_hash::MerkleHash;
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+use crate::node_store::NodeStore;
+use crate::proof_of_inclusion::ProofOfInclusion;
+
+/// The `ConsistencyProof` struct proves that a tree with `old_size` leaves is a prefix of a tree with `new_size` leaves, so the new tree was only appended to.
+///
+/// The proof is the last leaf of the old tree and the path from it to the root of the new tree. The left siblings in that path hold only leaves of the old tree, so hashing the leaf with them, and with itself where it is the last node of its level, gives the old root, and hashing it with every sibling gives the new root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsistencyProof {
+    pub old_size: usize,
+    pub new_size: usize,
+    pub leaf: MerkleHash,
+    pub path: Vec<MerkleHash>,
+}
+
+impl<S: NodeStore> MerkleTree<S> {
+    /// Returns a proof that the tree with the first `old_size` leaves of this one is a prefix of it.
+    ///
+    /// # Parameters
+    /// - `old_size`: The number of leaves of the old tree, between 1 and the number of leaves of this one
+    pub fn consistency_proof(&self, old_size: usize) -> Result<ConsistencyProof, MerkleTreeError> {
+        if old_size == 0 || old_size > self.len() {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "Can't prove the consistency of a tree of {} leaves with one of {}",
+                old_size,
+                self.len()
+            )));
+        }
+
+        let mut index = old_size - 1;
+        let leaf = self.digest(0, index)?;
+        let mut path = vec![];
+        for level in 0..self.height() - 1 {
+            let sibling = match index % 2 {
+                0 if index + 1 < self.level_len(level) => self.digest(level, index + 1)?,
+                0 => self.digest(level, index)?,
+                _ => self.digest(level, index - 1)?,
+            };
+            path.push(MerkleTree::digest_to_hex(&sibling));
+            index /= 2;
+        }
+
+        Ok(ConsistencyProof {
+            old_size,
+            new_size: self.len(),
+            leaf: MerkleTree::digest_to_hex(&leaf),
+            path,
+        })
+    }
+}
+
+impl ConsistencyProof {
+    /// Returns the roots of the old and the new tree obtained from the proof, or None if the proof doesn't have the length of a proof between trees of its sizes, or if a node on the right edge of the new tree doesn't have itself as its sibling.
+    pub fn roots(&self) -> Option<(MerkleHash, MerkleHash)> {
+        if self.old_size == 0
+            || self.old_size > self.new_size
+            || self.path.len() != MerkleTree::height_of(self.new_size) - 1
+        {
+            return None;
+        }
+
+        let old_height = MerkleTree::height_of(self.old_size);
+        let mut index = self.old_size - 1;
+        let mut old_root = self.leaf.clone();
+        let mut new_root = self.leaf.clone();
+
+        for (level, sibling) in self.path.iter().enumerate() {
+            if index % 2 == 1 {
+                new_root = MerkleTree::combine_hashes(sibling, &new_root);
+                if level + 1 < old_height {
+                    old_root = MerkleTree::combine_hashes(sibling, &old_root);
+                }
+            } else {
+                if index + 1 >= self.new_size.div_ceil(1 << level) && *sibling != new_root {
+                    return None;
+                }
+                new_root = MerkleTree::combine_hashes(&new_root, sibling);
+                if level + 1 < old_height {
+                    old_root = MerkleTree::combine_hashes(&old_root, &old_root);
+                }
+            }
+            index /= 2;
+        }
+        Some((old_root, new_root))
+    }
+
+    /// Verifies that the proof links the given old and new roots.
+    pub fn verify(&self, old_root: &MerkleHash, new_root: &MerkleHash) -> bool {
+        match self.roots() {
+            Some((old, new)) => old == *old_root && new == *new_root,
+            None => false,
+        }
+    }
+}
+
+impl ProofOfInclusion {
+    /// Returns the proof of inclusion of the same leaf in a bigger tree, from this proof in the old tree and a consistency proof between both trees, without needing the tree.
+    ///
+    /// The path of the last old leaf in the new tree, given by the consistency proof, holds every node of the new tree that covers leaves of the old tree and leaves appended after it. The other siblings of the leaf are roots of complete subtrees of the old tree, so they are taken from this proof. The refreshed proof leads to the new root of the consistency proof, which has to be checked against a trusted root.
+    ///
+    /// # Parameters
+    /// - `old_size`: The number of leaves of the tree this proof is for
+    /// - `new_size`: The number of leaves of the bigger tree
+    /// - `consistency`: A consistency proof between the trees, whose old root has to be the root of this proof
+    pub fn refresh(
+        &self,
+        old_size: usize,
+        new_size: usize,
+        consistency: &ConsistencyProof,
+    ) -> Result<ProofOfInclusion, MerkleTreeError> {
+        if consistency.old_size != old_size || consistency.new_size != new_size {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "The consistency proof is between {} and {} leaves, not {} and {}",
+                consistency.old_size, consistency.new_size, old_size, new_size
+            )));
+        }
+        let (old_root, _) = consistency.roots().ok_or_else(|| {
+            MerkleTreeError::InvalidValue("The consistency proof is malformed".to_string())
+        })?;
+        let old: Vec<&(MerkleHash, Direction)> = self.iter().collect();
+        if old.len() != MerkleTree::height_of(old_size) - 1 || self.root() != old_root {
+            return Err(MerkleTreeError::InvalidHash(
+                "The proof doesn't lead to the old root of the consistency proof".to_string(),
+            ));
+        }
+
+        let index = self.index();
+        let mut edge = consistency.leaf.clone();
+        let mut proof = vec![];
+        for (level, sibling) in consistency.path.iter().enumerate() {
+            let position = index >> level;
+            let last = (old_size - 1) >> level;
+
+            if position == last {
+                let direction = match last % 2 {
+                    1 => Direction::Left,
+                    _ => Direction::Right,
+                };
+                proof.push((sibling.clone(), direction));
+            } else if position % 2 == 1 || position + 1 < last {
+                proof.push(old[level].clone());
+            } else {
+                proof.push((edge.clone(), Direction::Right));
+            }
+
+            edge = match last % 2 {
+                1 => MerkleTree::combine_hashes(sibling, &edge),
+                _ => MerkleTree::combine_hashes(&edge, sibling),
+            };
+        }
+
+        Ok(ProofOfInclusion::new_from(self.leaf().clone(), proof))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::merkle_tree::{test_hashes, MerkleTree};
+
+    #[test]
+    fn consistency_proofs_link_every_prefix() {
+        let hashes = test_hashes("leaf", 37);
+        let tree = MerkleTree::new_from_hashes(hashes.clone()).unwrap();
+        let new_root = tree.root().unwrap();
+
+        for old_size in 1..=37 {
+            let old_root = MerkleTree::new_from_hashes(hashes[..old_size].to_vec())
+                .unwrap()
+                .root()
+                .unwrap();
+            let proof = tree.consistency_proof(old_size).unwrap();
+            assert!(proof.verify(&old_root, &new_root), "old size {}", old_size);
+            assert!(!proof.verify(&new_root, &old_root) || old_size == 37);
+        }
+        assert!(tree.consistency_proof(0).is_err());
+        assert!(tree.consistency_proof(38).is_err());
+    }
+
+    #[test]
+    fn old_proofs_are_refreshed() {
+        let tree = MerkleTree::new_from_hashes(test_hashes("leaf", 41)).unwrap();
+        let new_root = tree.root().unwrap();
+
+        for old_size in [1, 2, 3, 7, 8, 9, 20, 33, 41] {
+            let consistency = tree.consistency_proof(old_size).unwrap();
+            for index in 0..old_size {
+                let old = tree.proof_of_inclusion_at(index, old_size).unwrap();
+                assert_eq!(old.index(), index);
+
+                let refreshed = old.refresh(old_size, 41, &consistency).unwrap();
+                let expected = tree.proof_of_inclusion_at(index, 41).unwrap();
+                assert!(
+                    refreshed.iter().eq(expected.iter()),
+                    "{} of {}",
+                    index,
+                    old_size
+                );
+                assert!(refreshed.verify(&new_root));
+            }
+        }
+
+        let stale = tree.proof_of_inclusion_at(3, 10).unwrap();
+        assert!(stale
+            .refresh(12, 41, &tree.consistency_proof(12).unwrap())
+            .is_err());
+        assert!(stale
+            .refresh(10, 40, &tree.consistency_proof(10).unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn forked_trees_are_not_consistent() {
+        let mut hashes = test_hashes("leaf", 20);
+        let old_root = MerkleTree::new_from_hashes(hashes[..12].to_vec())
+            .unwrap()
+            .root()
+            .unwrap();
+
+        hashes[5] = MerkleTree::get_hash_of(&"rewritten");
+        let tree = MerkleTree::new_from_hashes(hashes).unwrap();
+        let proof = tree.consistency_proof(12).unwrap();
+        assert!(!proof.verify(&old_root, &tree.root().unwrap()));
+
+        let mut truncated = proof.clone();
+        truncated.path.pop();
+        assert!(truncated.roots().is_none());
+    }
+
+    #[test]
+    fn sizes_that_dont_match_the_right_edge_are_rejected() {
+        let tree = MerkleTree::new_from_hashes(test_hashes("leaf", 4)).unwrap();
+        let mut proof = tree.consistency_proof(3).unwrap();
+        assert!(proof.roots().is_some());
+
+        // Leaf 2 would be the last node of its level in a tree of 3 leaves, so its sibling would be itself, not leaf 3.
+        proof.new_size = 3;
+        assert!(proof.roots().is_none());
+    }
+}
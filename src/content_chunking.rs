@@ -0,0 +1,332 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::ops::Range;
+use std::path::Path;
+
+use sha3::{Digest, Sha3_256};
+
+use crate::merkle_hash::{MerkleDigest, MerkleHash};
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+
+/// Random values for every byte, used by the rolling hash. They are generated with SplitMix64 so they are always the same.
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x6a09e667f3bcc908;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// The minimum, average and maximum sizes of the chunks, in bytes. They are only built by `ChunkerParams::new`, which checks them, or by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkerParams {
+    min_size: usize,
+    avg_size: usize,
+    max_size: usize,
+}
+
+/// The `ContentChunker` struct splits data in chunks whose boundaries depend on their contents, as FastCDC does.
+///
+/// A gear rolling hash is computed over the bytes after the minimum size, and a chunk ends where the hash matches a mask. The mask is harder to match before the average size and easier after it, so chunk sizes stay close to the average, and a chunk always ends at the maximum size. As boundaries only depend on the bytes before them, inserting or removing bytes only changes the chunks around the edit.
+#[derive(Debug, Clone)]
+pub struct ContentChunker {
+    params: ChunkerParams,
+    mask_small: u64,
+    mask_large: u64,
+}
+
+/// A chunk of a `ContentChunkedFile`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentChunk {
+    pub offset: u64,
+    pub len: usize,
+    pub leaf: MerkleHash,
+}
+
+/// The `ContentChunkedFile` struct is a Merkle Tree whose leaves are the SHA3-256 hashes of the content-defined chunks of a file, so edited versions of a file share most of their leaves.
+#[derive(Debug)]
+pub struct ContentChunkedFile {
+    tree: MerkleTree,
+    chunks: Vec<ContentChunk>,
+}
+
+impl Default for ChunkerParams {
+    /// Chunks of 8 KiB on average, between 2 KiB and 64 KiB.
+    fn default() -> Self {
+        ChunkerParams {
+            min_size: 2 * 1024,
+            avg_size: 8 * 1024,
+            max_size: 64 * 1024,
+        }
+    }
+}
+
+impl ChunkerParams {
+    /// Creates the parameters of a chunker, checking that `0 < min_size <= avg_size <= max_size`.
+    pub fn new(
+        min_size: usize,
+        avg_size: usize,
+        max_size: usize,
+    ) -> Result<ChunkerParams, MerkleTreeError> {
+        if min_size == 0 || min_size > avg_size || avg_size > max_size {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "Invalid chunk sizes: min {}, avg {}, max {}",
+                min_size, avg_size, max_size
+            )));
+        }
+        Ok(ChunkerParams {
+            min_size,
+            avg_size,
+            max_size,
+        })
+    }
+
+    /// Returns the minimum size of the chunks, except for the last one.
+    pub fn min_size(&self) -> usize {
+        self.min_size
+    }
+
+    /// Returns the average size of the chunks.
+    pub fn avg_size(&self) -> usize {
+        self.avg_size
+    }
+
+    /// Returns the maximum size of the chunks.
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+}
+
+impl ContentChunker {
+    /// Creates a chunker with the given sizes.
+    pub fn new(params: ChunkerParams) -> ContentChunker {
+        let bits = usize::BITS - params.avg_size.leading_zeros() - 1;
+        ContentChunker {
+            params,
+            mask_small: ContentChunker::mask(bits + 2),
+            mask_large: ContentChunker::mask(bits.saturating_sub(2)),
+        }
+    }
+
+    /// Returns a mask with the given number of high bits set, which depend on the last 64 bytes hashed.
+    fn mask(bits: u32) -> u64 {
+        match bits {
+            0 => 0,
+            bits => u64::MAX << (64 - bits.min(64)),
+        }
+    }
+
+    /// Returns the size of the first chunk of the data, which must hold at least `max_size` bytes unless it is the end of the input.
+    pub fn cut_point(&self, data: &[u8]) -> usize {
+        let ChunkerParams {
+            min_size,
+            avg_size,
+            max_size,
+        } = self.params;
+        if data.len() <= min_size {
+            return data.len();
+        }
+
+        let limit = data.len().min(max_size);
+        let normal = avg_size.min(limit);
+        let mut hash: u64 = 0;
+        let mut i = min_size;
+
+        while i < normal {
+            hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+            if hash & self.mask_small == 0 {
+                return i + 1;
+            }
+            i += 1;
+        }
+        while i < limit {
+            hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+            if hash & self.mask_large == 0 {
+                return i + 1;
+            }
+            i += 1;
+        }
+        limit
+    }
+
+    /// Splits the contents of a reader in chunks, calling `on_chunk` with every one of them in order.
+    pub fn chunk_reader(
+        &self,
+        mut reader: impl Read,
+        mut on_chunk: impl FnMut(&[u8]),
+    ) -> Result<(), MerkleTreeError> {
+        let max_size = self.params.max_size;
+        let mut buffer = vec![0u8; 2 * max_size];
+        let mut start = 0;
+        let mut end = 0;
+        let mut eof = false;
+
+        loop {
+            if !eof && end - start < max_size {
+                buffer.copy_within(start..end, 0);
+                end -= start;
+                start = 0;
+                while !eof && end < buffer.len() {
+                    match reader.read(&mut buffer[end..]) {
+                        Ok(0) => eof = true,
+                        Ok(n) => end += n,
+                        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+                        Err(e) => return Err(MerkleTreeError::FailedToProcessFile(e.to_string())),
+                    }
+                }
+            }
+            if start == end {
+                return Ok(());
+            }
+
+            let cut = self.cut_point(&buffer[start..end]);
+            on_chunk(&buffer[start..start + cut]);
+            start += cut;
+        }
+    }
+}
+
+impl ContentChunkedFile {
+    /// Splits the contents of a reader in content-defined chunks and builds the tree of their hashes. Empty contents have a single empty chunk.
+    pub fn from_reader(
+        reader: impl Read,
+        params: ChunkerParams,
+    ) -> Result<ContentChunkedFile, MerkleTreeError> {
+        let mut chunks = vec![];
+        let mut leaves: Vec<MerkleDigest> = vec![];
+        let mut offset = 0;
+
+        ContentChunker::new(params).chunk_reader(reader, |chunk| {
+            let leaf: MerkleDigest = Sha3_256::digest(chunk).into();
+            chunks.push(ContentChunk {
+                offset,
+                len: chunk.len(),
+                leaf: MerkleTree::digest_to_hex(&leaf),
+            });
+            leaves.push(leaf);
+            offset += chunk.len() as u64;
+        })?;
+
+        if leaves.is_empty() {
+            let leaf: MerkleDigest = Sha3_256::digest(&[]).into();
+            chunks.push(ContentChunk {
+                offset: 0,
+                len: 0,
+                leaf: MerkleTree::digest_to_hex(&leaf),
+            });
+            leaves.push(leaf);
+        }
+
+        Ok(ContentChunkedFile {
+            tree: MerkleTree::new_from_digests(leaves)?,
+            chunks,
+        })
+    }
+
+    /// Builds the tree of the content-defined chunks of a file.
+    pub fn from_file(
+        path: impl AsRef<Path>,
+        params: ChunkerParams,
+    ) -> Result<ContentChunkedFile, MerkleTreeError> {
+        let file =
+            File::open(path).map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))?;
+        ContentChunkedFile::from_reader(BufReader::new(file), params)
+    }
+
+    /// Returns the root of the tree.
+    pub fn root(&self) -> Result<MerkleHash, MerkleTreeError> {
+        self.tree.root()
+    }
+
+    /// Returns the chunks of the file, in order.
+    pub fn chunks(&self) -> &[ContentChunk] {
+        &self.chunks
+    }
+
+    /// Returns the tree of the chunks.
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    /// Returns the byte ranges of this file whose chunks are not in an older version of it, merging adjacent chunks.
+    pub fn changed_regions(&self, old: &ContentChunkedFile) -> Vec<Range<u64>> {
+        let old_leaves: HashSet<&MerkleHash> = old.chunks.iter().map(|c| &c.leaf).collect();
+        let mut regions: Vec<Range<u64>> = vec![];
+
+        for chunk in self.chunks.iter().filter(|c| !old_leaves.contains(&c.leaf)) {
+            let end = chunk.offset + chunk.len as u64;
+            match regions.last_mut() {
+                Some(last) if last.end == chunk.offset => last.end = end,
+                _ => regions.push(chunk.offset..end),
+            }
+        }
+        regions
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::content_chunking::{ChunkerParams, ContentChunkedFile, ContentChunker};
+
+    fn random_bytes(len: usize) -> Vec<u8> {
+        let mut state: u64 = 0x2545f4914f6cdd1d;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chunks_respect_the_sizes_and_cover_the_data() {
+        let params = ChunkerParams::new(1024, 4096, 16384).unwrap();
+        let data = random_bytes(300_000);
+        let mut chunks = vec![];
+        ContentChunker::new(params)
+            .chunk_reader(data.as_slice(), |chunk| chunks.push(chunk.to_vec()))
+            .unwrap();
+
+        assert_eq!(chunks.concat(), data);
+        for chunk in chunks[..chunks.len() - 1].iter() {
+            assert!(chunk.len() >= 1024 && chunk.len() <= 16384);
+        }
+        let average = data.len() / chunks.len();
+        assert!(average > 2048 && average < 8192, "average {}", average);
+
+        assert!(ChunkerParams::new(4096, 1024, 16384).is_err());
+        assert!(ChunkerParams::new(0, 0, 0).is_err());
+        assert!(ChunkerParams::new(1024, 4096, 0).is_err());
+    }
+
+    #[test]
+    fn edits_only_change_the_chunks_around_them() {
+        let params = ChunkerParams::new(512, 2048, 8192).unwrap();
+        let original = random_bytes(200_000);
+        let mut edited = original.clone();
+        edited.splice(100_000..100_000, b"inserted bytes".iter().copied());
+
+        let old = ContentChunkedFile::from_reader(original.as_slice(), params).unwrap();
+        let new = ContentChunkedFile::from_reader(edited.as_slice(), params).unwrap();
+        assert_ne!(old.root().unwrap(), new.root().unwrap());
+
+        let regions = new.changed_regions(&old);
+        assert_eq!(regions.len(), 1);
+        assert!(regions[0].contains(&100_000));
+        assert!(regions[0].end - regions[0].start <= 3 * 8192);
+        assert!(new.changed_regions(&new).is_empty());
+    }
+}
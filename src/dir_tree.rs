@@ -0,0 +1,314 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use sha3::{Digest, Sha3_256};
+
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::merkle_hash::MerkleHash;
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+
+/// A file of a `DirTree`: its path relative to the directory, with `/` as separator, and its leaf.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub path: String,
+    pub leaf: MerkleHash,
+}
+
+/// The files that changed between two versions of a directory.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DirChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+/// The `DirTree` struct is a Merkle Tree that commits to every file of a directory.
+///
+/// The files are walked recursively and sorted by path, so the same contents always give the same root, and each one is a leaf with the SHA3-256 hash of its relative path, a zero byte and its contents. Symbolic links are skipped.
+#[derive(Debug)]
+pub struct DirTree {
+    files: Vec<DirEntry>,
+    tree: MerkleTree,
+}
+
+impl DirTree {
+    /// Hashes every file of a directory.
+    ///
+    /// # Parameters
+    /// - `dir`: The directory to hash
+    /// - `ignore`: Glob patterns, with `*` and `?`, of the files and directories to skip. Patterns with a `/` are matched against the relative path, the rest against every file or directory name.
+    pub fn from_dir(dir: impl AsRef<Path>, ignore: &[String]) -> Result<DirTree, MerkleTreeError> {
+        let dir = dir.as_ref();
+        let mut paths = vec![];
+        DirTree::walk(dir, "", ignore, &mut paths)?;
+        paths.sort();
+
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let leaf = DirTree::hash_file(&dir.join(&path), &path)?;
+                Ok(DirEntry { path, leaf })
+            })
+            .collect::<Result<Vec<_>, MerkleTreeError>>()?;
+
+        DirTree::from_entries(files)
+    }
+
+    /// Builds the tree of a list of files sorted by path.
+    fn from_entries(files: Vec<DirEntry>) -> Result<DirTree, MerkleTreeError> {
+        if files.is_empty() {
+            return Err(MerkleTreeError::FailedToBuild(
+                "The directory has no files".to_string(),
+            ));
+        }
+        let tree = MerkleTree::new_from_hashes(files.iter().map(|f| f.leaf.clone()).collect())?;
+        Ok(DirTree { files, tree })
+    }
+
+    /// Collects the relative paths of the files inside `dir`, prefixing them with `prefix`.
+    fn walk(
+        dir: &Path,
+        prefix: &str,
+        ignore: &[String],
+        paths: &mut Vec<String>,
+    ) -> Result<(), MerkleTreeError> {
+        let entries =
+            fs::read_dir(dir).map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))?;
+
+        for entry in entries {
+            let entry = entry.map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let path = format!("{}{}", prefix, name);
+            if DirTree::is_ignored(&path, &name, ignore) {
+                continue;
+            }
+
+            let file_type = entry
+                .file_type()
+                .map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))?;
+            if file_type.is_dir() {
+                DirTree::walk(&entry.path(), &format!("{}/", path), ignore, paths)?;
+            } else if file_type.is_file() {
+                paths.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    fn is_ignored(path: &str, name: &str, ignore: &[String]) -> bool {
+        ignore.iter().any(|pattern| match pattern.contains('/') {
+            true => DirTree::glob_match(pattern.trim_matches('/').as_bytes(), path.as_bytes()),
+            false => DirTree::glob_match(pattern.as_bytes(), name.as_bytes()),
+        })
+    }
+
+    /// Matches a text against a pattern where `*` is any sequence of characters and `?` is any single character.
+    fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+        match (pattern.first(), text.first()) {
+            (None, None) => true,
+            (Some(b'*'), _) => {
+                DirTree::glob_match(&pattern[1..], text)
+                    || (!text.is_empty() && DirTree::glob_match(pattern, &text[1..]))
+            }
+            (Some(b'?'), Some(_)) => DirTree::glob_match(&pattern[1..], &text[1..]),
+            (Some(p), Some(t)) if p == t => DirTree::glob_match(&pattern[1..], &text[1..]),
+            _ => false,
+        }
+    }
+
+    /// Returns the hash of the path of a file, a zero byte and its contents.
+    fn hash_file(file: &Path, path: &str) -> Result<MerkleHash, MerkleTreeError> {
+        let mut hasher = Sha3_256::new();
+        hasher.update(path.as_bytes());
+        hasher.update([0u8]);
+
+        let mut file =
+            File::open(file).map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))?;
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            match file.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => hasher.update(&buffer[..n]),
+                Err(e) => return Err(MerkleTreeError::FailedToProcessFile(e.to_string())),
+            }
+        }
+        Ok(MerkleTree::bytes_to_hex(&hasher.finalize()))
+    }
+
+    /// Returns the root of the tree.
+    pub fn root(&self) -> Result<MerkleHash, MerkleTreeError> {
+        self.tree.root()
+    }
+
+    /// Returns the files of the directory, sorted by path.
+    pub fn files(&self) -> &[DirEntry] {
+        &self.files
+    }
+
+    /// Returns the tree of the files.
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    /// Returns the entries of a manifest of the directory, labeled with the path of every file.
+    pub fn entries(&self) -> Vec<ManifestEntry> {
+        self.files
+            .iter()
+            .map(|file| ManifestEntry {
+                label: file.path.clone(),
+                leaf: file.leaf.clone(),
+            })
+            .collect()
+    }
+
+    /// Returns the manifest of the directory, to detect changes later.
+    pub fn manifest(&self) -> Result<Manifest, MerkleTreeError> {
+        Manifest::new(self.entries())
+    }
+
+    /// Builds the tree of a directory from its manifest, whose entries must be sorted by path.
+    pub fn from_manifest(manifest: &Manifest) -> Result<DirTree, MerkleTreeError> {
+        let files: Vec<DirEntry> = manifest
+            .entries
+            .iter()
+            .map(|entry| DirEntry {
+                path: entry.label.clone(),
+                leaf: entry.leaf.clone(),
+            })
+            .collect();
+        if files.windows(2).any(|pair| pair[0].path >= pair[1].path) {
+            return Err(MerkleTreeError::InvalidValue(
+                "The entries of the manifest are not sorted by path".to_string(),
+            ));
+        }
+        DirTree::from_entries(files)
+    }
+
+    /// Lists the files added, removed and modified since an older version of the directory.
+    ///
+    /// The trees are compared with `MerkleTree::diff`, which skips the subtrees whose hashes match, as they hold the same files with the same contents. Only the leaves left are compared by path.
+    pub fn changes_since(&self, old: &DirTree) -> Result<DirChanges, MerkleTreeError> {
+        let differing = self.tree.diff(&old.tree)?;
+        let old_left: Vec<usize> = differing
+            .iter()
+            .flat_map(|range| range.start..range.end.min(old.files.len()))
+            .collect();
+        let new_left: Vec<usize> = differing
+            .iter()
+            .flat_map(|range| range.start..range.end.min(self.files.len()))
+            .collect();
+
+        let mut changes = DirChanges::default();
+        let (mut i, mut j) = (0, 0);
+        while i < old_left.len() || j < new_left.len() {
+            let old_file = old_left.get(i).map(|&k| &old.files[k]);
+            let new_file = new_left.get(j).map(|&k| &self.files[k]);
+            match (old_file, new_file) {
+                (Some(o), Some(n)) if o.path == n.path => {
+                    if o.leaf != n.leaf {
+                        changes.modified.push(n.path.clone());
+                    }
+                    i += 1;
+                    j += 1;
+                }
+                (Some(o), Some(n)) if o.path < n.path => {
+                    changes.removed.push(o.path.clone());
+                    i += 1;
+                }
+                (Some(o), None) => {
+                    changes.removed.push(o.path.clone());
+                    i += 1;
+                }
+                (_, Some(n)) => {
+                    changes.added.push(n.path.clone());
+                    j += 1;
+                }
+                (None, None) => break,
+            }
+        }
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::dir_tree::{DirChanges, DirTree};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("merkle-dir-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src/nested")).unwrap();
+        fs::create_dir_all(dir.join("target")).unwrap();
+        for i in 0..12 {
+            fs::write(
+                dir.join(format!("src/file{:02}.txt", i)),
+                format!("file {}", i),
+            )
+            .unwrap();
+        }
+        fs::write(dir.join("src/nested/deep.txt"), "deep").unwrap();
+        fs::write(dir.join("target/build.log"), "ignored").unwrap();
+        fs::write(dir.join("notes.log"), "ignored").unwrap();
+        dir
+    }
+
+    #[test]
+    fn hashing_is_deterministic_and_skips_ignored_files() {
+        let dir = temp_dir("hash");
+        let ignore = vec!["target".to_string(), "*.log".to_string()];
+        let tree = DirTree::from_dir(&dir, &ignore).unwrap();
+
+        assert_eq!(tree.files().len(), 13);
+        assert_eq!(tree.files()[0].path, "src/file00.txt");
+        assert_eq!(tree.files()[12].path, "src/nested/deep.txt");
+        assert_eq!(
+            tree.root().unwrap(),
+            DirTree::from_dir(&dir, &ignore).unwrap().root().unwrap()
+        );
+
+        let all = DirTree::from_dir(&dir, &[]).unwrap();
+        assert_eq!(all.files().len(), 15);
+
+        let reloaded = DirTree::from_manifest(&tree.manifest().unwrap()).unwrap();
+        assert_eq!(reloaded.files(), tree.files());
+        assert_eq!(reloaded.root().unwrap(), tree.root().unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn changes_are_listed_against_a_manifest() {
+        let dir = temp_dir("changes");
+        let ignore = vec!["target".to_string(), "*.log".to_string()];
+        let old = DirTree::from_manifest(
+            &DirTree::from_dir(&dir, &ignore)
+                .unwrap()
+                .manifest()
+                .unwrap(),
+        )
+        .unwrap();
+
+        fs::write(dir.join("src/file03.txt"), "changed").unwrap();
+        fs::remove_file(dir.join("src/file07.txt")).unwrap();
+        fs::write(dir.join("src/file07b.txt"), "new").unwrap();
+        fs::write(dir.join("zzz.txt"), "new").unwrap();
+
+        let new = DirTree::from_dir(&dir, &ignore).unwrap();
+        assert_eq!(
+            new.changes_since(&old).unwrap(),
+            DirChanges {
+                added: vec!["src/file07b.txt".to_string(), "zzz.txt".to_string()],
+                removed: vec!["src/file07.txt".to_string()],
+                modified: vec!["src/file03.txt".to_string()],
+            }
+        );
+        assert_eq!(old.changes_since(&old).unwrap(), DirChanges::default());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
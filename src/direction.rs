@@ -1,5 +1,8 @@
+use serde::{Deserialize, Serialize};
+
 /// The Direction enum represents whether a hash must be concatenated to the left or to the right.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Direction {
     Left,
     Right,
@@ -0,0 +1,375 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::file_store::FileStore;
+use crate::journal::{Journal, JournalRecord, JournalTail};
+use crate::merkle_hash::{MerkleDigest, MerkleHash};
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+use crate::node_store::NodeStore;
+
+/// Name of the journal file inside the directory of the tree.
+const JOURNAL_FILE: &str = "journal.log";
+
+/// Name of the directory that keeps the nodes of the tree.
+const NODES_DIR: &str = "nodes";
+
+/// Number of leaves replayed at once when the nodes are rebuilt from the journal.
+const REPLAY_CHUNK: usize = 1 << 16;
+
+/// What was found and done when a `DurableTree` was opened.
+#[derive(Debug)]
+pub struct Recovery {
+    /// Number of leaves of the recovered tree.
+    pub leaves: usize,
+    /// Number of leaves of the last checkpoint found in the journal, if any.
+    pub checkpoint: Option<usize>,
+    /// Number of leaves written after the last checkpoint that were replayed from the journal.
+    pub replayed: usize,
+    /// True if the stored nodes couldn't be trusted and the whole tree was rebuilt from the journal.
+    pub rebuilt: bool,
+    /// The state of the end of the journal. A truncated or corrupted tail is discarded.
+    pub tail: JournalTail,
+}
+
+/// An append-only Merkle Tree kept in a directory that survives crashes.
+///
+/// Every leaf is written to a journal, and synced to disk, before it is added to the nodes, which are kept in a `FileStore`. `checkpoint` syncs the nodes and then journals the root, so on restart the nodes are trusted up to the last checkpoint whose root they match and the leaves written after it are replayed from the journal. If the nodes don't match, the tree is rebuilt from the journal. A journal tail that was not completely written, or that is corrupted, is detected with the checksum of every record and discarded.
+#[derive(Debug)]
+pub struct DurableTree {
+    dir: PathBuf,
+    tree: MerkleTree<FileStore>,
+    journal: Journal,
+    checkpoint: Option<usize>,
+}
+
+/// The records of a journal that are needed to recover the tree.
+#[derive(Default)]
+struct JournalSummary {
+    leaves: usize,
+    checkpoint: Option<(usize, MerkleDigest)>,
+    pending: Vec<MerkleDigest>,
+}
+
+impl DurableTree {
+    /// Opens the tree kept in the given directory, creating it if it doesn't exist, and recovers it to the last leaf that was completely journaled.
+    pub fn open(dir: impl AsRef<Path>) -> Result<(DurableTree, Recovery), MerkleTreeError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(DurableTree::io_error)?;
+        let journal_path = dir.join(JOURNAL_FILE);
+
+        let mut summary = JournalSummary::default();
+        let scan = Journal::scan(&journal_path, |record| {
+            match record {
+                JournalRecord::Leaf(digest) => {
+                    summary.leaves += 1;
+                    summary.pending.push(digest);
+                }
+                JournalRecord::Checkpoint { size, root } => {
+                    let size = size as usize;
+                    if size > summary.leaves {
+                        return Err(MerkleTreeError::StorageError(format!(
+                            "Checkpoint of {} leaves found after only {} leaves",
+                            size, summary.leaves
+                        )));
+                    }
+                    summary.checkpoint = Some((size, root));
+                    summary.pending.clear();
+                }
+            }
+            Ok(())
+        })?;
+
+        let checkpoint_size = summary.checkpoint.map(|(size, _)| size);
+        let (tree, replayed, rebuilt) = match DurableTree::trusted_nodes(&dir, &summary) {
+            Some(mut tree) => {
+                let replayed = summary.pending.len();
+                tree.append_digests(summary.pending)?;
+                (tree, replayed, false)
+            }
+            None => {
+                let tree = DurableTree::rebuild(&dir, &journal_path, &summary)?;
+                (tree, summary.leaves - checkpoint_size.unwrap_or(0), true)
+            }
+        };
+
+        let journal = Journal::open(&journal_path, scan.valid_len)?;
+        let recovery = Recovery {
+            leaves: tree.len(),
+            checkpoint: checkpoint_size,
+            replayed,
+            rebuilt,
+            tail: scan.tail,
+        };
+        let durable = DurableTree {
+            dir,
+            tree,
+            journal,
+            checkpoint: checkpoint_size,
+        };
+        Ok((durable, recovery))
+    }
+
+    /// Opens the stored nodes and cuts them to the last checkpoint, if their root matches it.
+    fn trusted_nodes(dir: &Path, summary: &JournalSummary) -> Option<MerkleTree<FileStore>> {
+        let store = FileStore::open(dir.join(NODES_DIR)).ok()?;
+        let (size, root) = summary.checkpoint.unwrap_or((0, [0u8; 32]));
+        if store.leaf_count() < size {
+            return None;
+        }
+
+        let mut tree = MerkleTree::from_store(store);
+        tree.truncate(size).ok()?;
+        match size {
+            0 => Some(tree),
+            _ => (tree.root_digest().ok()? == root).then_some(tree),
+        }
+    }
+
+    /// Discards the stored nodes and builds them again from every leaf of the journal.
+    fn rebuild(
+        dir: &Path,
+        journal_path: &Path,
+        summary: &JournalSummary,
+    ) -> Result<MerkleTree<FileStore>, MerkleTreeError> {
+        let nodes = dir.join(NODES_DIR);
+        if nodes.exists() {
+            fs::remove_dir_all(&nodes).map_err(DurableTree::io_error)?;
+        }
+        let mut tree = MerkleTree::from_store(FileStore::open(&nodes)?);
+
+        let mut chunk = Vec::with_capacity(REPLAY_CHUNK);
+        let mut read = 0;
+        Journal::scan(journal_path, |record| {
+            if let JournalRecord::Leaf(digest) = record {
+                if read < summary.leaves {
+                    chunk.push(digest);
+                    read += 1;
+                }
+                if chunk.len() == REPLAY_CHUNK {
+                    tree.append_digests(std::mem::take(&mut chunk))?;
+                }
+            }
+            Ok(())
+        })?;
+        tree.append_digests(chunk)?;
+
+        if let Some((size, root)) = summary.checkpoint {
+            if size > 0 && tree.root_at(size)? != MerkleTree::digest_to_hex(&root) {
+                return Err(MerkleTreeError::StorageError(
+                    "The journal doesn't match its last checkpoint".to_string(),
+                ));
+            }
+        }
+        tree.flush()?;
+        Ok(tree)
+    }
+
+    /// Returns the directory of the tree.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the tree, to query it for proofs.
+    pub fn tree(&self) -> &MerkleTree<FileStore> {
+        &self.tree
+    }
+
+    /// Returns the number of leaves of the tree.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns true if the tree has no leaves.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Returns the number of leaves of the last checkpoint, if any.
+    pub fn last_checkpoint(&self) -> Option<usize> {
+        self.checkpoint
+    }
+
+    /// Returns the root of the tree.
+    pub fn root(&self) -> Result<MerkleHash, MerkleTreeError> {
+        self.tree.root()
+    }
+
+    /// Appends a hash to the tree. The hash is synced to the journal before the nodes are updated, so once it returns the leaf survives a crash. If the nodes can't be updated, the leaf is taken out of the journal again.
+    /// Unlike `MerkleTree::add_hash`, and like `MerkleTree::append_hashes`, it doesn't check whether the hash is already in the tree, as that would read every leaf from disk on every append.
+    ///
+    /// # Parameters
+    /// - `hash`: The hash to add to the tree
+    pub fn add_hash(&mut self, hash: MerkleHash) -> Result<(), MerkleTreeError> {
+        let digest = MerkleTree::hex_to_digest(&hash)?;
+        let journaled = self.journal.valid_len();
+        let len = self.tree.len();
+        self.journal.append(&JournalRecord::Leaf(digest))?;
+        if let Err(e) = self.tree.append_digests(vec![digest]) {
+            self.journal.truncate(journaled)?;
+            self.tree.truncate(len)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Adds an element that will be hashed before adding it to the tree.
+    pub fn add_data(&mut self, data: impl AsRef<[u8]>) -> Result<(), MerkleTreeError> {
+        self.add_hash(MerkleTree::get_hash_of(&data))
+    }
+
+    /// Syncs the nodes to disk and journals the current root, so the next recovery doesn't need to replay the leaves written until now.
+    pub fn checkpoint(&mut self) -> Result<MerkleHash, MerkleTreeError> {
+        let root = self.tree.root_digest()?;
+        self.tree.flush()?;
+        self.journal.append(&JournalRecord::Checkpoint {
+            size: self.len() as u64,
+            root,
+        })?;
+        self.checkpoint = Some(self.len());
+        Ok(MerkleTree::digest_to_hex(&root))
+    }
+
+    fn io_error(e: std::io::Error) -> MerkleTreeError {
+        MerkleTreeError::StorageError(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{self, OpenOptions};
+    use std::path::PathBuf;
+
+    use crate::durable_tree::{DurableTree, JOURNAL_FILE, NODES_DIR};
+    use crate::journal::{Journal, JournalRecord, JournalTail};
+    use crate::merkle_tree::MerkleTree;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("merkle-durable-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn root_of(len: usize) -> String {
+        let hashes = (0..len)
+            .map(|i| MerkleTree::get_hash_of(&format!("leaf{}", i)))
+            .collect();
+        MerkleTree::new_from_hashes(hashes).unwrap().root().unwrap()
+    }
+
+    fn fill(tree: &mut DurableTree, range: std::ops::Range<usize>) {
+        for i in range {
+            tree.add_data(format!("leaf{}", i)).unwrap();
+        }
+    }
+
+    #[test]
+    fn reopened_tree_keeps_checkpointed_and_pending_leaves() {
+        let dir = temp_dir("reopen");
+        let (mut tree, recovery) = DurableTree::open(&dir).unwrap();
+        assert_eq!(recovery.leaves, 0);
+        fill(&mut tree, 0..20);
+        assert_eq!(tree.checkpoint().unwrap(), root_of(20));
+        fill(&mut tree, 20..27);
+        tree.add_data("leaf3").unwrap();
+        drop(tree);
+
+        let (tree, recovery) = DurableTree::open(&dir).unwrap();
+        assert_eq!(recovery.leaves, 28);
+        assert_eq!(recovery.checkpoint, Some(20));
+        assert_eq!(recovery.replayed, 8);
+        assert!(!recovery.rebuilt);
+        assert_eq!(recovery.tail, JournalTail::Clean);
+        let mut hashes: Vec<String> = (0..27)
+            .map(|i| MerkleTree::get_hash_of(&format!("leaf{}", i)))
+            .collect();
+        hashes.push(hashes[3].clone());
+        let expected = MerkleTree::new_from_hashes(hashes).unwrap();
+        assert_eq!(tree.root().unwrap(), expected.root().unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn torn_and_corrupted_tails_are_discarded() {
+        let dir = temp_dir("tails");
+        let (mut tree, _) = DurableTree::open(&dir).unwrap();
+        fill(&mut tree, 0..10);
+        drop(tree);
+
+        let journal = dir.join(JOURNAL_FILE);
+        let len = fs::metadata(&journal).unwrap().len();
+        let file = OpenOptions::new().write(true).open(&journal).unwrap();
+        file.set_len(len - 5).unwrap();
+        drop(file);
+
+        let (mut tree, recovery) = DurableTree::open(&dir).unwrap();
+        assert_eq!(recovery.tail, JournalTail::Truncated { discarded: 32 });
+        assert_eq!(tree.root().unwrap(), root_of(9));
+        fill(&mut tree, 9..12);
+        drop(tree);
+
+        let mut bytes = fs::read(&journal).unwrap();
+        let offset = bytes.len() - 37 * 2;
+        bytes[offset + 3] ^= 0xff;
+        fs::write(&journal, bytes).unwrap();
+
+        let (tree, recovery) = DurableTree::open(&dir).unwrap();
+        assert_eq!(
+            recovery.tail,
+            JournalTail::Corrupted {
+                offset: offset as u64,
+                discarded: 74
+            }
+        );
+        assert_eq!(tree.root().unwrap(), root_of(10));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn lost_nodes_are_rebuilt_from_the_journal() {
+        let dir = temp_dir("rebuild");
+        let (mut tree, _) = DurableTree::open(&dir).unwrap();
+        fill(&mut tree, 0..15);
+        tree.checkpoint().unwrap();
+        fill(&mut tree, 15..18);
+        drop(tree);
+
+        fs::remove_dir_all(dir.join(NODES_DIR)).unwrap();
+
+        let (tree, recovery) = DurableTree::open(&dir).unwrap();
+        assert!(recovery.rebuilt);
+        assert_eq!(recovery.leaves, 18);
+        assert_eq!(tree.root().unwrap(), root_of(18));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn discarded_journal_records_are_not_replayed() {
+        let dir = temp_dir("discard");
+        let (mut tree, _) = DurableTree::open(&dir).unwrap();
+        fill(&mut tree, 0..5);
+        drop(tree);
+
+        let path = dir.join(JOURNAL_FILE);
+        let scan = Journal::scan(&path, |_| Ok(())).unwrap();
+        let mut journal = Journal::open(&path, scan.valid_len).unwrap();
+        assert_eq!(journal.valid_len(), scan.valid_len);
+        journal
+            .append(&JournalRecord::Leaf(
+                MerkleTree::hex_to_digest(&MerkleTree::get_hash_of(&"leaf5")).unwrap(),
+            ))
+            .unwrap();
+        journal.truncate(scan.valid_len).unwrap();
+        drop(journal);
+
+        let (tree, recovery) = DurableTree::open(&dir).unwrap();
+        assert_eq!(recovery.tail, JournalTail::Clean);
+        assert_eq!(tree.root().unwrap(), root_of(5));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,287 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::merkle_hash::MerkleDigest;
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+use crate::node_store::NodeStore;
+
+/// Name of the file that keeps the number of leaves.
+const LEAF_COUNT_FILE: &str = "leaves";
+
+/// Name of the file the number of leaves is written to before it replaces `LEAF_COUNT_FILE`.
+const LEAF_COUNT_TEMP_FILE: &str = "leaves.tmp";
+
+/// Number of levels below the root whose nodes are kept in memory once read. They hold less than 2^17 nodes, 4 MiB, whatever the size of the tree, and are read by every proof.
+const CACHED_LEVELS: usize = 16;
+
+/// A `NodeStore` that keeps the nodes in files inside a directory, one file per level, so trees bigger than the memory can be used.
+///
+/// Nodes of a level are stored one after the other, so appending a leaf only writes at the end of the files, and batches of nodes are written at once. The nodes of the top levels are cached in memory once read, as they are on the path of every proof. The number of leaves is written to a temporary file and renamed over the old one, so a crash never leaves it half written.
+#[derive(Debug)]
+pub struct FileStore {
+    dir: PathBuf,
+    levels: Vec<File>,
+    leaf_count: usize,
+    cache: RefCell<HashMap<(usize, usize), MerkleDigest>>,
+}
+
+impl FileStore {
+    /// Opens the store in the given directory, creating it if it doesn't exist.
+    pub fn open(dir: impl AsRef<Path>) -> Result<FileStore, MerkleTreeError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(FileStore::io_error)?;
+
+        let leaf_count = match fs::read(dir.join(LEAF_COUNT_FILE)) {
+            Ok(bytes) => {
+                let bytes: [u8; 8] = bytes.as_slice().try_into().map_err(|_| {
+                    MerkleTreeError::StorageError("Invalid leaf count file".to_string())
+                })?;
+                u64::from_le_bytes(bytes) as usize
+            }
+            Err(_) => 0,
+        };
+
+        let mut store = FileStore {
+            dir,
+            levels: vec![],
+            leaf_count: 0,
+            cache: RefCell::new(HashMap::new()),
+        };
+        store.open_levels(leaf_count)?;
+        store.leaf_count = leaf_count;
+        Ok(store)
+    }
+
+    /// Returns the directory of the store.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Opens, creating them if needed, the files of every level of a tree with the given number of leaves.
+    fn open_levels(&mut self, leaf_count: usize) -> Result<(), MerkleTreeError> {
+        while self.levels.len() < MerkleTree::height_of(leaf_count) {
+            let path = self.dir.join(format!("level-{:02}.bin", self.levels.len()));
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+                .map_err(FileStore::io_error)?;
+            self.levels.push(file);
+        }
+        Ok(())
+    }
+
+    /// Returns true if the nodes of a level are kept in the cache.
+    fn is_cached(&self, level: usize) -> bool {
+        level + CACHED_LEVELS >= MerkleTree::height_of(self.leaf_count)
+    }
+
+    /// Writes the number of leaves to a temporary file, syncs it and renames it over the leaf count file.
+    fn write_leaf_count(&self, leaf_count: usize) -> Result<(), MerkleTreeError> {
+        let temporary = self.dir.join(LEAF_COUNT_TEMP_FILE);
+        let mut file = File::create(&temporary).map_err(FileStore::io_error)?;
+        file.write_all(&(leaf_count as u64).to_le_bytes())
+            .and_then(|_| file.sync_all())
+            .and_then(|_| fs::rename(&temporary, self.dir.join(LEAF_COUNT_FILE)))
+            .map_err(FileStore::io_error)
+    }
+
+    fn level_len(&self, level: usize) -> usize {
+        if level >= MerkleTree::height_of(self.leaf_count) {
+            return 0;
+        }
+        self.leaf_count.div_ceil(1 << level)
+    }
+
+    fn check_range(&self, level: usize, start: usize, len: usize) -> Result<(), MerkleTreeError> {
+        if start + len > self.level_len(level) {
+            return Err(MerkleTreeError::StorageError(format!(
+                "There is no node at level {} and index {}",
+                level,
+                start + len - 1
+            )));
+        }
+        Ok(())
+    }
+
+    fn io_error(e: std::io::Error) -> MerkleTreeError {
+        MerkleTreeError::StorageError(e.to_string())
+    }
+}
+
+impl NodeStore for FileStore {
+    fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    fn set_leaf_count(&mut self, leaf_count: usize) -> Result<(), MerkleTreeError> {
+        self.open_levels(leaf_count)?;
+
+        if leaf_count < self.leaf_count {
+            for (level, file) in self.levels.iter().enumerate() {
+                let len = match level < MerkleTree::height_of(leaf_count) {
+                    true => leaf_count.div_ceil(1 << level),
+                    false => 0,
+                };
+                file.set_len(32 * len as u64).map_err(FileStore::io_error)?;
+            }
+        }
+
+        self.write_leaf_count(leaf_count)?;
+        let previous = std::mem::replace(&mut self.leaf_count, leaf_count);
+        // The cached levels change with the height, and the nodes of dropped leaves go away.
+        if leaf_count < previous
+            || MerkleTree::height_of(leaf_count) != MerkleTree::height_of(previous)
+        {
+            let levels: Vec<bool> = (0..self.levels.len()).map(|l| self.is_cached(l)).collect();
+            self.cache.borrow_mut().retain(|(level, index), _| {
+                levels[*level] && *index < leaf_count.div_ceil(1 << level)
+            });
+        }
+        Ok(())
+    }
+
+    fn get(&self, level: usize, index: usize) -> Result<MerkleDigest, MerkleTreeError> {
+        if let Some(digest) = self.cache.borrow().get(&(level, index)) {
+            return Ok(*digest);
+        }
+
+        let digest = self.get_batch(level, index, 1)?[0];
+        if self.is_cached(level) {
+            self.cache.borrow_mut().insert((level, index), digest);
+        }
+        Ok(digest)
+    }
+
+    fn put(
+        &mut self,
+        level: usize,
+        index: usize,
+        digest: MerkleDigest,
+    ) -> Result<(), MerkleTreeError> {
+        self.put_batch(level, index, &[digest])
+    }
+
+    fn get_batch(
+        &self,
+        level: usize,
+        start: usize,
+        len: usize,
+    ) -> Result<Vec<MerkleDigest>, MerkleTreeError> {
+        if len == 0 {
+            return Ok(vec![]);
+        }
+        self.check_range(level, start, len)?;
+
+        let mut bytes = vec![0u8; 32 * len];
+        let mut file = &self.levels[level];
+        file.seek(SeekFrom::Start(32 * start as u64))
+            .and_then(|_| file.read_exact(&mut bytes))
+            .map_err(FileStore::io_error)?;
+
+        Ok(bytes
+            .chunks_exact(32)
+            .map(|chunk| chunk.try_into().unwrap_or([0u8; 32]))
+            .collect())
+    }
+
+    fn put_batch(
+        &mut self,
+        level: usize,
+        start: usize,
+        digests: &[MerkleDigest],
+    ) -> Result<(), MerkleTreeError> {
+        if digests.is_empty() {
+            return Ok(());
+        }
+        self.check_range(level, start, digests.len())?;
+
+        let bytes = digests.concat();
+        let mut file = &self.levels[level];
+        file.seek(SeekFrom::Start(32 * start as u64))
+            .and_then(|_| file.write_all(&bytes))
+            .map_err(FileStore::io_error)?;
+
+        if self.is_cached(level) {
+            let mut cache = self.cache.borrow_mut();
+            for (i, digest) in digests.iter().enumerate() {
+                cache.insert((level, start + i), *digest);
+            }
+        }
+        Ok(())
+    }
+
+    /// Syncs the levels, and then the directory so new files and the last rename of the leaf count file are kept too.
+    fn flush(&mut self) -> Result<(), MerkleTreeError> {
+        for file in self.levels.iter() {
+            file.sync_data().map_err(FileStore::io_error)?;
+        }
+        File::open(&self.dir)
+            .and_then(|dir| dir.sync_all())
+            .map_err(FileStore::io_error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::file_store::FileStore;
+    use crate::merkle_tree::{test_hashes, MerkleTree};
+    use crate::node_store::NodeStore;
+
+    #[test]
+    fn file_tree_matches_memory_tree_after_reopening() {
+        let dir = std::env::temp_dir().join(format!("merkle-file-store-{}", std::process::id()));
+        let hashes = test_hashes("something", 1500);
+
+        let mut tree = MerkleTree::from_store(FileStore::open(&dir).unwrap());
+        tree.append_hashes(hashes[..1000].to_vec()).unwrap();
+        tree.flush().unwrap();
+        drop(tree);
+
+        let mut tree = MerkleTree::from_store(FileStore::open(&dir).unwrap());
+        assert_eq!(tree.len(), 1000);
+        tree.append_hashes(hashes[1000..1499].to_vec()).unwrap();
+        tree.add_hash(hashes[1499].clone()).unwrap();
+
+        let memory = MerkleTree::new_from_hashes(hashes.clone()).unwrap();
+        assert_eq!(tree.root().unwrap(), memory.root().unwrap());
+        assert!(tree.verify_with_index(&hashes[777], 777));
+        assert!(tree.verify(&hashes[1499]));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_the_top_levels_are_cached() {
+        let dir = std::env::temp_dir().join(format!("merkle-file-cache-{}", std::process::id()));
+        let mut store = FileStore::open(&dir).unwrap();
+        store.set_leaf_count((1 << 17) + 1).unwrap();
+        for level in [0, 2, 3, 18] {
+            store.put(level, 0, [level as u8; 32]).unwrap();
+            assert_eq!(store.get(level, 0).unwrap(), [level as u8; 32]);
+        }
+        let mut cached: Vec<usize> = store
+            .cache
+            .borrow()
+            .keys()
+            .map(|(level, _)| *level)
+            .collect();
+        cached.sort_unstable();
+        assert_eq!(cached, vec![3, 18]);
+
+        // A leaf count that was being written when the process died is ignored.
+        fs::write(dir.join("leaves.tmp"), [1, 2, 3]).unwrap();
+        drop(store);
+        assert_eq!(FileStore::open(&dir).unwrap().leaf_count(), (1 << 17) + 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
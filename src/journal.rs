@@ -0,0 +1,259 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::path::Path;
+
+use crate::merkle_hash::MerkleDigest;
+use crate::merkle_tree_error::MerkleTreeError;
+
+/// Bytes at the start of every journal file.
+const MAGIC: &[u8; 8] = b"MRKLJRN1";
+
+const LEAF_TAG: u8 = 1;
+const CHECKPOINT_TAG: u8 = 2;
+
+/// A record of the journal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JournalRecord {
+    /// A leaf appended to the tree.
+    Leaf(MerkleDigest),
+    /// The root of the tree once it had `size` leaves, written after the nodes were persisted.
+    Checkpoint { size: u64, root: MerkleDigest },
+}
+
+/// The state of the end of a journal, after its last valid record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JournalTail {
+    /// The journal ends right after its last record.
+    Clean,
+    /// The last record was not completely written, usually because the process died while writing it. Its bytes are discarded.
+    Truncated { discarded: u64 },
+    /// A record has an unknown tag or its checksum doesn't match. It and everything after it is discarded.
+    Corrupted { offset: u64, discarded: u64 },
+}
+
+/// The result of scanning a journal: how many bytes of it are valid and what was found after them.
+#[derive(Debug)]
+pub struct JournalScan {
+    pub valid_len: u64,
+    pub tail: JournalTail,
+}
+
+/// An append-only write-ahead journal of leaves and checkpoints.
+///
+/// Every record is a tag, its payload and the CRC32 of both, and is synced to disk before `append` returns, so a record that can be read back was completely written.
+#[derive(Debug)]
+pub struct Journal {
+    file: File,
+    valid_len: u64,
+}
+
+impl JournalRecord {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        match self {
+            JournalRecord::Leaf(digest) => {
+                bytes.push(LEAF_TAG);
+                bytes.extend_from_slice(digest);
+            }
+            JournalRecord::Checkpoint { size, root } => {
+                bytes.push(CHECKPOINT_TAG);
+                bytes.extend_from_slice(&size.to_le_bytes());
+                bytes.extend_from_slice(root);
+            }
+        }
+        let crc = crc32fast::hash(&bytes);
+        bytes.extend_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    fn payload_len(tag: u8) -> Option<usize> {
+        match tag {
+            LEAF_TAG => Some(32),
+            CHECKPOINT_TAG => Some(8 + 32),
+            _ => None,
+        }
+    }
+
+    fn decode(tag: u8, payload: &[u8]) -> JournalRecord {
+        let mut root = [0u8; 32];
+        match tag {
+            LEAF_TAG => {
+                root.copy_from_slice(payload);
+                JournalRecord::Leaf(root)
+            }
+            _ => {
+                let mut size = [0u8; 8];
+                size.copy_from_slice(&payload[..8]);
+                root.copy_from_slice(&payload[8..]);
+                JournalRecord::Checkpoint {
+                    size: u64::from_le_bytes(size),
+                    root,
+                }
+            }
+        }
+    }
+}
+
+impl Journal {
+    /// Reads every valid record of the journal at `path`, in order, stopping at the first truncated or corrupted one.
+    /// A journal that doesn't exist is empty.
+    ///
+    /// # Parameters
+    /// - `path`: The path of the journal
+    /// - `on_record`: Called with every valid record
+    pub fn scan(
+        path: &Path,
+        mut on_record: impl FnMut(JournalRecord) -> Result<(), MerkleTreeError>,
+    ) -> Result<JournalScan, MerkleTreeError> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Ok(JournalScan {
+                    valid_len: 0,
+                    tail: JournalTail::Clean,
+                })
+            }
+            Err(e) => return Err(Journal::io_error(e)),
+        };
+        let file_len = file.metadata().map_err(Journal::io_error)?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0u8; 8];
+        if file_len < MAGIC.len() as u64 {
+            return Ok(JournalScan {
+                valid_len: 0,
+                tail: JournalTail::Truncated {
+                    discarded: file_len,
+                },
+            });
+        }
+        reader.read_exact(&mut magic).map_err(Journal::io_error)?;
+        if &magic != MAGIC {
+            return Err(MerkleTreeError::StorageError(
+                "The file is not a journal".to_string(),
+            ));
+        }
+
+        let mut offset = MAGIC.len() as u64;
+        let mut record = [0u8; 1 + 8 + 32 + 4];
+
+        loop {
+            let corrupted = JournalScan {
+                valid_len: offset,
+                tail: JournalTail::Corrupted {
+                    offset,
+                    discarded: file_len - offset,
+                },
+            };
+            let truncated = JournalScan {
+                valid_len: offset,
+                tail: JournalTail::Truncated {
+                    discarded: file_len - offset,
+                },
+            };
+
+            if offset == file_len {
+                return Ok(JournalScan {
+                    valid_len: offset,
+                    tail: JournalTail::Clean,
+                });
+            }
+
+            reader
+                .read_exact(&mut record[..1])
+                .map_err(Journal::io_error)?;
+            let payload_len = match JournalRecord::payload_len(record[0]) {
+                Some(len) => len,
+                None => return Ok(corrupted),
+            };
+
+            let record_len = 1 + payload_len + 4;
+            if offset + record_len as u64 > file_len {
+                return Ok(truncated);
+            }
+            reader
+                .read_exact(&mut record[1..record_len])
+                .map_err(Journal::io_error)?;
+
+            let mut crc = [0u8; 4];
+            crc.copy_from_slice(&record[1 + payload_len..record_len]);
+            if crc32fast::hash(&record[..1 + payload_len]) != u32::from_le_bytes(crc) {
+                return Ok(corrupted);
+            }
+
+            on_record(JournalRecord::decode(
+                record[0],
+                &record[1..1 + payload_len],
+            ))?;
+            offset += record_len as u64;
+        }
+    }
+
+    /// Opens the journal at `path` to append records, discarding everything after its first `valid_len` bytes.
+    /// The journal is created if it doesn't exist, and its directory is synced so the new file survives a crash.
+    pub fn open(path: &Path, valid_len: u64) -> Result<Journal, MerkleTreeError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(Journal::io_error)?;
+
+        let valid_len = match valid_len < MAGIC.len() as u64 {
+            true => {
+                file.set_len(0).map_err(Journal::io_error)?;
+                file.write_all(MAGIC).map_err(Journal::io_error)?;
+                MAGIC.len() as u64
+            }
+            false => {
+                file.set_len(valid_len).map_err(Journal::io_error)?;
+                valid_len
+            }
+        };
+        file.sync_all().map_err(Journal::io_error)?;
+
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(directory)
+            .and_then(|directory| directory.sync_all())
+            .map_err(Journal::io_error)?;
+
+        Ok(Journal { file, valid_len })
+    }
+
+    /// Returns the number of bytes of the journal, all of them written by complete records.
+    pub fn valid_len(&self) -> u64 {
+        self.valid_len
+    }
+
+    /// Appends a record and syncs it to disk. If it can't be written, whatever part of it was written is discarded.
+    pub fn append(&mut self, record: &JournalRecord) -> Result<(), MerkleTreeError> {
+        let bytes = record.encode();
+        let written = self
+            .file
+            .write_all(&bytes)
+            .and_then(|_| self.file.sync_data());
+        if let Err(e) = written {
+            let _ = self.file.set_len(self.valid_len);
+            return Err(Journal::io_error(e));
+        }
+        self.valid_len += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Discards every record after the first `valid_len` bytes, e.g. the last one when it couldn't be applied.
+    pub fn truncate(&mut self, valid_len: u64) -> Result<(), MerkleTreeError> {
+        self.file
+            .set_len(valid_len)
+            .and_then(|_| self.file.sync_data())
+            .map_err(Journal::io_error)?;
+        self.valid_len = valid_len;
+        Ok(())
+    }
+
+    fn io_error(e: std::io::Error) -> MerkleTreeError {
+        MerkleTreeError::StorageError(e.to_string())
+    }
+}
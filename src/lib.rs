@@ -1,7 +1,34 @@
+pub mod abi_type;
+pub mod allowlist;
+pub mod chunked_file;
 pub mod cli;
-mod direction;
-mod merkle_hash;
-mod merkle_tree;
+pub mod consistency_proof;
+pub mod content_chunking;
+pub mod dir_tree;
+pub mod direction;
+pub mod durable_tree;
+pub mod file_store;
+pub mod journal;
+pub mod light_client;
+pub mod manifest;
+pub mod memory_store;
+pub mod merkle_hash;
+pub mod merkle_tree;
 pub mod merkle_tree_error;
-mod proof_of_inclusion;
+pub mod node_store;
+pub mod proof_of_inclusion;
+pub mod pruned_tree;
+pub mod range_proof;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod signed_tree_head;
+pub mod sorted_pair_tree;
+pub mod streaming_builder;
+pub mod subtree;
+pub mod sync;
+pub mod tlog_tiles;
+pub mod tree_checkpoint;
+pub mod tree_diff;
+pub mod tree_mode;
 pub mod util;
+pub mod witness;
@@ -0,0 +1,240 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::consistency_proof::ConsistencyProof;
+use crate::merkle_hash::MerkleHash;
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+use crate::proof_of_inclusion::ProofOfInclusion;
+
+/// The size and root a `LightClient` trusts, as kept in its state file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustedRoot {
+    pub size: usize,
+    pub root: MerkleHash,
+}
+
+/// The `LightClient` struct follows a growing tree holding only its size and root, for devices that can't keep the tree.
+///
+/// It moves to a new root only when a consistency proof shows the new tree extends the one it trusts, and verifies proofs of inclusion against the root it trusts. When it is opened from a state file, every accepted root is written to it before `update` returns, replacing the file atomically so a crash leaves either the old state or the new one.
+#[derive(Debug)]
+pub struct LightClient {
+    trusted: TrustedRoot,
+    path: Option<PathBuf>,
+}
+
+impl LightClient {
+    /// Creates a client that trusts the given root, without a state file.
+    pub fn new(size: usize, root: MerkleHash) -> Result<LightClient, MerkleTreeError> {
+        MerkleTree::hex_to_digest(&root)?;
+        if size == 0 {
+            return Err(MerkleTreeError::InvalidValue(
+                "A trusted tree has at least one leaf".to_string(),
+            ));
+        }
+        Ok(LightClient {
+            trusted: TrustedRoot { size, root },
+            path: None,
+        })
+    }
+
+    /// Creates a client that trusts the given root and keeps it in a new state file.
+    pub fn create(
+        path: impl AsRef<Path>,
+        size: usize,
+        root: MerkleHash,
+    ) -> Result<LightClient, MerkleTreeError> {
+        let mut client = LightClient::new(size, root)?;
+        client.path = Some(path.as_ref().to_path_buf());
+        client.save()?;
+        Ok(client)
+    }
+
+    /// Opens a client from its state file.
+    pub fn open(path: impl AsRef<Path>) -> Result<LightClient, MerkleTreeError> {
+        let json = std::fs::read_to_string(&path)
+            .map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))?;
+        let trusted: TrustedRoot = serde_json::from_str(&json)
+            .map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))?;
+
+        let mut client = LightClient::new(trusted.size, trusted.root)?;
+        client.path = Some(path.as_ref().to_path_buf());
+        Ok(client)
+    }
+
+    /// Returns the size and root the client trusts.
+    pub fn trusted(&self) -> &TrustedRoot {
+        &self.trusted
+    }
+
+    /// Writes the trusted root to a temporary file next to the state file and renames it over the state file, syncing the directory so the rename survives a crash.
+    fn save(&self) -> Result<(), MerkleTreeError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let json = serde_json::to_string_pretty(&self.trusted)
+            .map_err(|e| MerkleTreeError::InvalidValue(e.to_string()))?;
+        let temporary = path.with_extension("tmp");
+
+        let write = || -> std::io::Result<()> {
+            let mut file = File::create(&temporary)?;
+            file.write_all(json.as_bytes())?;
+            file.sync_all()?;
+            std::fs::rename(&temporary, path)?;
+
+            let directory = match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            File::open(directory)?.sync_all()
+        };
+        write().map_err(|e| MerkleTreeError::FailedToWriteFile(e.to_string()))
+    }
+
+    /// Moves to a new root if the consistency proof shows its tree extends the trusted one, persisting it.
+    ///
+    /// The size of the proof is not authenticated by it, so the size of the new tree has to come from the same source as its root, e.g. a signed tree head.
+    ///
+    /// # Parameters
+    /// - `new_size`: The number of leaves of the bigger tree
+    /// - `new_root`: The root of the bigger tree
+    /// - `proof`: A consistency proof from the trusted size to the size of the new tree
+    pub fn update(
+        &mut self,
+        new_size: usize,
+        new_root: &MerkleHash,
+        proof: &ConsistencyProof,
+    ) -> Result<(), MerkleTreeError> {
+        if proof.old_size != self.trusted.size || proof.new_size != new_size {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "The consistency proof is between {} and {} leaves, not {} and {}",
+                proof.old_size, proof.new_size, self.trusted.size, new_size
+            )));
+        }
+        if !proof.verify(&self.trusted.root, new_root) {
+            return Err(MerkleTreeError::InvalidHash(
+                "The new root is not consistent with the trusted one".to_string(),
+            ));
+        }
+
+        let previous = std::mem::replace(
+            &mut self.trusted,
+            TrustedRoot {
+                size: new_size,
+                root: new_root.clone(),
+            },
+        );
+        if let Err(e) = self.save() {
+            self.trusted = previous;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Verifies that a proof of inclusion leads to the trusted root and is the proof of a leaf of a tree of the trusted size.
+    ///
+    /// Proofs of leaves past the last one are rejected by their index, see [`ProofOfInclusion::index`].
+    pub fn verify_inclusion(&self, proof: &ProofOfInclusion) -> bool {
+        let height = MerkleTree::height_of(self.trusted.size);
+        proof.iter().count() == height - 1
+            && proof.index() < self.trusted.size
+            && proof.verify(&self.trusted.root)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::direction::Direction;
+    use crate::light_client::LightClient;
+    use crate::merkle_tree::MerkleTree;
+    use crate::proof_of_inclusion::ProofOfInclusion;
+
+    #[test]
+    fn roots_are_only_accepted_with_consistency_proofs() {
+        let dir = std::env::temp_dir().join(format!("merkle-light-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("client.json");
+
+        let hashes: Vec<String> = (0..30)
+            .map(|i| MerkleTree::get_hash_of(&format!("leaf{}", i)))
+            .collect();
+        let mut tree = MerkleTree::new_from_hashes(hashes[..10].to_vec()).unwrap();
+        let mut client = LightClient::create(&path, 10, tree.root().unwrap()).unwrap();
+        assert!(client.verify_inclusion(&tree.proof_of_inclusion(&hashes[4]).unwrap()));
+
+        tree.append_hashes(hashes[10..].to_vec()).unwrap();
+        let proof = tree.consistency_proof(10).unwrap();
+        let forged = MerkleTree::get_hash_of(&"forged");
+        assert!(client.update(30, &forged, &proof).is_err());
+        assert!(client
+            .update(
+                30,
+                &tree.root().unwrap(),
+                &tree.consistency_proof(11).unwrap()
+            )
+            .is_err());
+        assert!(client.update(32, &tree.root().unwrap(), &proof).is_err());
+        assert_eq!(client.trusted().size, 10);
+
+        client.update(30, &tree.root().unwrap(), &proof).unwrap();
+        let proof_of_inclusion = tree.proof_of_inclusion(&hashes[25]).unwrap();
+        assert!(client.verify_inclusion(&proof_of_inclusion));
+
+        let reopened = LightClient::open(&path).unwrap();
+        assert_eq!(reopened.trusted(), client.trusted());
+        assert!(reopened.verify_inclusion(&proof_of_inclusion));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn the_new_size_comes_from_the_caller() {
+        let hashes: Vec<String> = (0..5)
+            .map(|i| MerkleTree::get_hash_of(&format!("leaf{}", i)))
+            .collect();
+        let old = MerkleTree::new_from_hashes(hashes[..3].to_vec()).unwrap();
+        let tree = MerkleTree::new_from_hashes(hashes).unwrap();
+        let mut client = LightClient::new(3, old.root().unwrap()).unwrap();
+
+        let mut relabelled = tree.consistency_proof(3).unwrap();
+        relabelled.new_size = 8;
+        assert!(relabelled.verify(&old.root().unwrap(), &tree.root().unwrap()));
+        assert!(client
+            .update(5, &tree.root().unwrap(), &relabelled)
+            .is_err());
+        assert_eq!(client.trusted().size, 3);
+
+        client
+            .update(
+                5,
+                &tree.root().unwrap(),
+                &tree.consistency_proof(3).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(client.trusted().size, 5);
+    }
+
+    #[test]
+    fn proofs_past_the_last_leaf_are_rejected() {
+        let hashes: Vec<String> = (0..10)
+            .map(|i| MerkleTree::get_hash_of(&format!("leaf{}", i)))
+            .collect();
+        let tree = MerkleTree::new_from_hashes(hashes.clone()).unwrap();
+        let client = LightClient::new(10, tree.root().unwrap()).unwrap();
+
+        // A proof of a leaf past the last one, see `ProofOfInclusion::index`.
+        let proof = tree.proof_of_inclusion_with_index(&hashes[8], 8).unwrap();
+        let mut path: Vec<(String, Direction)> = proof.iter().cloned().collect();
+        path[1] = (tree.node(1, 4).unwrap(), Direction::Left);
+        let forged = ProofOfInclusion::new_from(hashes[8].clone(), path);
+
+        assert_eq!(forged.index(), 10);
+        assert!(forged.verify(&tree.root().unwrap()));
+        assert!(client.verify_inclusion(&proof));
+        assert!(!client.verify_inclusion(&forged));
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::merkle_hash::MerkleHash;
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+use crate::tree_mode::{HashAlgorithm, TreeMode};
+
+/// Version of the manifests written by this library. Manifests of newer versions are rejected.
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// A leaf of a manifest with its label, e.g. the name of a file or the ID of a record.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub label: String,
+    pub leaf: MerkleHash,
+}
+
+/// The entries of a manifest that don't match the current data.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ManifestReport {
+    /// Labels whose leaf changed.
+    pub mismatched: Vec<String>,
+    /// Labels of the manifest that are not in the data anymore.
+    pub missing: Vec<String>,
+    /// Labels of the data that are not in the manifest.
+    pub unexpected: Vec<String>,
+}
+
+/// The `Manifest` struct is a portable description of a tree: how it was built, its root and the label and leaf of every entry, in order.
+///
+/// It is written as JSON with a version number, so readers can reject the formats they don't know, and it is checked when read: the leaf count and the root must match the entries.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub version: u32,
+    pub hash_algorithm: HashAlgorithm,
+    pub tree_mode: TreeMode,
+    pub leaf_count: usize,
+    pub root: MerkleHash,
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl ManifestReport {
+    /// Returns true if every entry matches.
+    pub fn is_clean(&self) -> bool {
+        self.mismatched.is_empty() && self.missing.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+impl Manifest {
+    /// Creates the manifest of a list of entries, computing the root of their leaves.
+    pub fn new(entries: Vec<ManifestEntry>) -> Result<Manifest, MerkleTreeError> {
+        let root = Manifest::tree_of(&entries)?.root()?;
+        Ok(Manifest {
+            version: MANIFEST_VERSION,
+            hash_algorithm: HashAlgorithm::Sha3_256,
+            tree_mode: TreeMode::HexPairsDuplicateOdd,
+            leaf_count: entries.len(),
+            root,
+            entries,
+        })
+    }
+
+    /// Creates the manifest of a list of records, one per line, whose ID is their first comma-separated field and whose leaf is the hash of the whole line.
+    pub fn from_records(records: &str) -> Result<Manifest, MerkleTreeError> {
+        Manifest::new(Manifest::record_entries(records))
+    }
+
+    /// Returns the entries of a list of records, as `from_records` does.
+    pub fn record_entries(records: &str) -> Vec<ManifestEntry> {
+        records
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| ManifestEntry {
+                label: line
+                    .split(',')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string(),
+                leaf: MerkleTree::get_hash_of(&line),
+            })
+            .collect()
+    }
+
+    fn tree_of(entries: &[ManifestEntry]) -> Result<MerkleTree, MerkleTreeError> {
+        let mut seen = HashMap::new();
+        for (i, entry) in entries.iter().enumerate() {
+            if let Some(first) = seen.insert(&entry.label, i) {
+                return Err(MerkleTreeError::InvalidValue(format!(
+                    "The label {:?} of entry {} was already used by entry {}",
+                    entry.label, i, first
+                )));
+            }
+        }
+        MerkleTree::new_from_hashes(entries.iter().map(|e| e.leaf.clone()).collect())
+    }
+
+    /// Returns the tree of the leaves of the manifest.
+    pub fn tree(&self) -> Result<MerkleTree, MerkleTreeError> {
+        Manifest::tree_of(&self.entries)
+    }
+
+    /// Returns the manifest as JSON.
+    pub fn to_json(&self) -> Result<String, MerkleTreeError> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| MerkleTreeError::FailedToWriteFile(e.to_string()))
+    }
+
+    /// Reads a manifest from JSON, checking its version and that its leaf count and root match its entries.
+    pub fn from_json(json: &str) -> Result<Manifest, MerkleTreeError> {
+        let version = serde_json::from_str::<serde_json::Value>(json)
+            .map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))?["version"]
+            .as_u64();
+        match version {
+            Some(version) if version <= MANIFEST_VERSION as u64 => (),
+            Some(version) => {
+                return Err(MerkleTreeError::InvalidValue(format!(
+                    "Unsupported manifest version {}",
+                    version
+                )))
+            }
+            None => {
+                return Err(MerkleTreeError::InvalidValue(
+                    "The manifest has no version".to_string(),
+                ))
+            }
+        }
+
+        let manifest: Manifest = serde_json::from_str(json)
+            .map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))?;
+        if manifest.hash_algorithm != HashAlgorithm::Sha3_256
+            || manifest.tree_mode != TreeMode::HexPairsDuplicateOdd
+        {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "Unsupported manifest tree: {} in {:?} mode",
+                manifest.hash_algorithm.name(),
+                manifest.tree_mode
+            )));
+        }
+        if manifest.leaf_count != manifest.entries.len() {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "The manifest has {} entries but a leaf count of {}",
+                manifest.entries.len(),
+                manifest.leaf_count
+            )));
+        }
+        if manifest.tree()?.root()? != manifest.root {
+            return Err(MerkleTreeError::InvalidHash(
+                "The entries of the manifest don't match its root".to_string(),
+            ));
+        }
+        Ok(manifest)
+    }
+
+    /// Compares the manifest with the current entries of the data it describes, reporting exactly which labels no longer match.
+    pub fn verify(&self, current: &[ManifestEntry]) -> ManifestReport {
+        let current_leaves: HashMap<&String, &MerkleHash> =
+            current.iter().map(|e| (&e.label, &e.leaf)).collect();
+        let labels: HashSet<&String> = self.entries.iter().map(|e| &e.label).collect();
+
+        let mut report = ManifestReport::default();
+        for entry in self.entries.iter() {
+            match current_leaves.get(&entry.label) {
+                Some(leaf) if **leaf == entry.leaf => (),
+                Some(_) => report.mismatched.push(entry.label.clone()),
+                None => report.missing.push(entry.label.clone()),
+            }
+        }
+        for entry in current.iter() {
+            if !labels.contains(&entry.label) {
+                report.unexpected.push(entry.label.clone());
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::manifest::{Manifest, ManifestReport};
+
+    const RECORDS: &str = "1,alice,10\n2,bob,20\n3,carol,30\n4,dave,40\n5,erin,50\n";
+
+    #[test]
+    fn manifests_are_written_and_read_back() {
+        let manifest = Manifest::from_records(RECORDS).unwrap();
+        assert_eq!(manifest.leaf_count, 5);
+        assert_eq!(manifest.entries[1].label, "2");
+
+        let json = manifest.to_json().unwrap();
+        assert!(json.contains("\"hashAlgorithm\": \"sha3-256\""));
+        assert_eq!(Manifest::from_json(&json).unwrap(), manifest);
+
+        let tampered = json.replace(&manifest.entries[0].leaf, &manifest.entries[1].leaf);
+        assert!(Manifest::from_json(&tampered).is_err());
+        let newer = json.replace("\"version\": 1", "\"version\": 2");
+        assert!(Manifest::from_json(&newer).is_err());
+        assert!(Manifest::from_records("1,a\n1,b\n").is_err());
+    }
+
+    #[test]
+    fn verification_reports_the_entries_that_changed() {
+        let manifest = Manifest::from_records(RECORDS).unwrap();
+        assert!(manifest
+            .verify(&Manifest::record_entries(RECORDS))
+            .is_clean());
+
+        let changed = "1,alice,10\n2,bob,21\n4,dave,40\n5,erin,50\n6,frank,60\n";
+        assert_eq!(
+            manifest.verify(&Manifest::record_entries(changed)),
+            ManifestReport {
+                mismatched: vec!["2".to_string()],
+                missing: vec!["3".to_string()],
+                unexpected: vec!["6".to_string()],
+            }
+        );
+    }
+}
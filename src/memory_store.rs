@@ -0,0 +1,144 @@
+use crate::merkle_hash::MerkleDigest;
+use crate::merkle_tree_error::MerkleTreeError;
+use crate::node_store::NodeStore;
+
+/// A `NodeStore` that keeps every node in memory.
+///
+/// The nodes are stored in a single contiguous array, level after level starting from the leaves. Every level has room for `capacity >> level` nodes, so the offset of each level is computed from the capacity, and the array is only reallocated when the number of leaves goes over it.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    nodes: Vec<MerkleDigest>,
+    leaf_count: usize,
+    capacity: usize,
+}
+
+impl MemoryStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    /// Returns the position in the nodes array of the first node of the given level.
+    fn level_offset(capacity: usize, level: usize) -> usize {
+        2 * capacity - ((2 * capacity) >> level)
+    }
+
+    /// Returns the number of levels there is room for with the given capacity, a power of two.
+    fn levels(capacity: usize) -> usize {
+        (usize::BITS - capacity.leading_zeros()) as usize
+    }
+
+    /// Returns the number of nodes in a level of a tree with the given number of leaves.
+    fn level_len(leaf_count: usize, level: usize) -> usize {
+        if level >= usize::BITS as usize {
+            return 0;
+        }
+        leaf_count.div_ceil(1 << level)
+    }
+
+    /// Returns the position in the nodes array of the given node, if there is room for it.
+    fn position(&self, level: usize, index: usize) -> Result<usize, MerkleTreeError> {
+        if level >= MemoryStore::levels(self.capacity)
+            || index >= MemoryStore::level_len(self.leaf_count, level)
+        {
+            return Err(MerkleTreeError::StorageError(format!(
+                "There is no node at level {} and index {}",
+                level, index
+            )));
+        }
+        Ok(MemoryStore::level_offset(self.capacity, level) + index)
+    }
+
+    /// Changes the capacity of the store, moving every level to its new offset.
+    fn relayout(&mut self, capacity: usize) {
+        let mut nodes = vec![[0u8; 32]; 2 * capacity - 1];
+        for level in 0..MemoryStore::levels(capacity) {
+            let from = MemoryStore::level_offset(self.capacity, level);
+            let to = MemoryStore::level_offset(capacity, level);
+            let len = MemoryStore::level_len(self.leaf_count, level).min(self.capacity >> level);
+            if len > 0 {
+                nodes[to..to + len].copy_from_slice(&self.nodes[from..from + len]);
+            }
+        }
+
+        self.nodes = nodes;
+        self.capacity = capacity;
+    }
+}
+
+impl NodeStore for MemoryStore {
+    fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    fn set_leaf_count(&mut self, leaf_count: usize) -> Result<(), MerkleTreeError> {
+        if leaf_count > self.capacity {
+            self.relayout(leaf_count.next_power_of_two());
+        }
+        self.leaf_count = leaf_count;
+        Ok(())
+    }
+
+    fn get(&self, level: usize, index: usize) -> Result<MerkleDigest, MerkleTreeError> {
+        Ok(self.nodes[self.position(level, index)?])
+    }
+
+    fn put(
+        &mut self,
+        level: usize,
+        index: usize,
+        digest: MerkleDigest,
+    ) -> Result<(), MerkleTreeError> {
+        let position = self.position(level, index)?;
+        self.nodes[position] = digest;
+        Ok(())
+    }
+
+    fn get_batch(
+        &self,
+        level: usize,
+        start: usize,
+        len: usize,
+    ) -> Result<Vec<MerkleDigest>, MerkleTreeError> {
+        if len == 0 {
+            return Ok(vec![]);
+        }
+        let first = self.position(level, start)?;
+        self.position(level, start + len - 1)?;
+        Ok(self.nodes[first..first + len].to_vec())
+    }
+
+    fn put_batch(
+        &mut self,
+        level: usize,
+        start: usize,
+        digests: &[MerkleDigest],
+    ) -> Result<(), MerkleTreeError> {
+        if digests.is_empty() {
+            return Ok(());
+        }
+        let first = self.position(level, start)?;
+        self.position(level, start + digests.len() - 1)?;
+        self.nodes[first..first + digests.len()].copy_from_slice(digests);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memory_store::MemoryStore;
+    use crate::node_store::NodeStore;
+
+    #[test]
+    fn nodes_above_the_root_are_errors() {
+        let mut store = MemoryStore::new();
+        store.set_leaf_count(5).unwrap();
+        store.put(3, 0, [1; 32]).unwrap();
+        assert_eq!(store.get(3, 0).unwrap(), [1; 32]);
+
+        assert!(store.get(4, 0).is_err());
+        assert!(store.put(10, 0, [1; 32]).is_err());
+        assert!(store.get(usize::BITS as usize, 0).is_err());
+        assert!(store.get_batch(5, 0, 1).is_err());
+    }
+}
@@ -1,2 +1,5 @@
 /// The MerkleHash type is a string that must be a created by doing a SHA256 hash of an element.
 pub type MerkleHash = String;
+
+/// The MerkleDigest type is the raw form of a `MerkleHash`, the 32 bytes of the digest it represents in hexadecimal.
+pub type MerkleDigest = [u8; 32];
@@ -1,107 +1,470 @@
+use std::thread;
+
 use sha3::{Digest, Sha3_256};
 
 use crate::direction::Direction;
+use crate::memory_store::MemoryStore;
 use crate::merkle_tree_error::MerkleTreeError;
+use crate::node_store::NodeStore;
 use crate::proof_of_inclusion::ProofOfInclusion;
+use crate::tree_checkpoint::TreeCheckpoint;
+
+use super::merkle_hash::{MerkleDigest, MerkleHash};
 
-use super::merkle_hash::MerkleHash;
+/// Number of hashes to compute in a step (leaves or pairs of a level) from which the work is split across threads.
+const PARALLEL_THRESHOLD: usize = 4096;
+
+/// Maximum number of nodes read from the store at once while building the tree, so big trees can be built in stores that don't fit in memory.
+const BUILD_CHUNK: usize = 1 << 16;
 
 /// A Merkle Tree implementation
 ///
+/// The nodes are kept in a `NodeStore`, by default a `MemoryStore` that keeps all of them in a single contiguous array. When a level has an odd number of nodes, the last one is hashed with itself to obtain its parent.
+///
 /// # Methods
 /// - `new_from_hashes`: Creates a new MerkleTree from a list of hashes.
 /// - `new_from_hasables`: Creates a new MerkleTree from a list of objects that are hashable.
+/// - `from_store`: Creates a MerkleTree from the nodes of a store, like a `FileStore`.
 /// - `root`: Returns the root of the Merkle Tree, which is the Merkle Root.
+/// - `node`: Returns the hash of the node at a given level and index.
 /// - `verify`: Verifies that a given hash is contained in the Merkle Tree.
 /// - `proof_of_inclusion`: Returns a proof of inclusion for a given hash in the Merkle Tree.
+/// - `root_at` and `proof_of_inclusion_at`: Return the root and the proofs the tree had when it had fewer leaves.
+/// - `checkpoint` and `rewind`: Name the current state of the tree and go back to it.
+/// - `merge`: Appends the leaves of another tree, reusing the nodes of both trees.
 #[derive(Debug)]
-pub struct MerkleTree {
-    levels: Vec<Vec<MerkleHash>>,
+pub struct MerkleTree<S: NodeStore = MemoryStore> {
+    store: S,
+    pub(crate) checkpoints: Vec<TreeCheckpoint>,
 }
 
 impl MerkleTree {
     /// Creates a new MerkleTree from a list of hashes.
     pub fn new_from_hashes(hashes: Vec<MerkleHash>) -> Result<MerkleTree, MerkleTreeError> {
-        let mut tree = MerkleTree { levels: vec![] };
-        MerkleTree::build_tree(&mut tree, hashes)?;
-        Ok(tree)
+        let leaves = hashes
+            .iter()
+            .map(|hash| MerkleTree::hex_to_digest(hash))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        MerkleTree::new_from_digests(leaves)
     }
 
     /// Creates a new MerkleTree from a list of objects that can be converted to byte slices (== that are hashable).
-    pub fn new_from_hashables(data: Vec<impl AsRef<[u8]>>) -> Result<MerkleTree, MerkleTreeError> {
+    /// Big lists are hashed across several threads.
+    pub fn new_from_hashables(
+        data: Vec<impl AsRef<[u8]> + Sync>,
+    ) -> Result<MerkleTree, MerkleTreeError> {
+        let mut leaves = vec![[0u8; 32]; data.len()];
+        MerkleTree::parallel_fill(&mut leaves, |i| Sha3_256::digest(data[i].as_ref()).into());
+
+        MerkleTree::new_from_digests(leaves)
+    }
+
+    /// Creates a new MerkleTree from a list of raw leaf digests.
+    pub(crate) fn new_from_digests(
+        leaves: Vec<MerkleDigest>,
+    ) -> Result<MerkleTree, MerkleTreeError> {
+        if leaves.is_empty() {
+            return Err(MerkleTreeError::FailedToBuild(
+                "Expected at least one hash".to_string(),
+            ));
+        }
+
+        let mut tree = MerkleTree::from_store(MemoryStore::new());
+        tree.append_digests(leaves)?;
+        Ok(tree)
+    }
+
+    /// Fills `out` with `f(i)` for every index, splitting the work in contiguous chunks across threads when there are at least `PARALLEL_THRESHOLD` items.
+    /// Every item is computed by the same function, so the results are the same as the ones of the single-threaded path.
+    fn parallel_fill<T: Send>(out: &mut [T], f: impl Fn(usize) -> T + Sync) {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+
+        if out.len() < PARALLEL_THRESHOLD || threads == 1 {
+            for (i, item) in out.iter_mut().enumerate() {
+                *item = f(i);
+            }
+            return;
+        }
+
+        let chunk_size = out.len().div_ceil(threads);
+        let f = &f;
+
+        thread::scope(|scope| {
+            for (chunk_index, chunk) in out.chunks_mut(chunk_size).enumerate() {
+                scope.spawn(move || {
+                    let start = chunk_index * chunk_size;
+                    for (i, item) in chunk.iter_mut().enumerate() {
+                        *item = f(start + i);
+                    }
+                });
+            }
+        });
+    }
+
+    /// Concatenates two hashes and returns the hash of the concatenation.
+    pub(crate) fn combine_hashes(left: &MerkleHash, right: &MerkleHash) -> MerkleHash {
+        let mut combined = left.clone();
+        combined.push_str(right);
+
         let mut hasher = Sha3_256::new();
-        let hashes = data
-            .iter()
-            .map(|d| {
-                hasher.update(d);
-                let result = hasher.finalize_reset();
-                MerkleTree::bytes_to_hex(&result)
-            })
-            .collect();
+        hasher.update(combined);
+        let result = hasher.finalize();
+
+        MerkleTree::bytes_to_hex(&result)
+    }
+
+    /// Returns the digest of the concatenation of the hex representation of two digests, the same as `combine_hashes` does with their hashes.
+    pub(crate) fn combine(left: &MerkleDigest, right: &MerkleDigest) -> MerkleDigest {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+
+        let mut combined = [0u8; 128];
+        for (i, byte) in left.iter().chain(right.iter()).enumerate() {
+            combined[2 * i] = HEX[(byte >> 4) as usize];
+            combined[2 * i + 1] = HEX[(byte & 0x0f) as usize];
+        }
+
+        Sha3_256::digest(&combined).into()
+    }
+
+    /// Returns the hash of the given data
+    ///
+    /// # Parameters
+    /// - `data`: An object that can be converted to a byte slice
+    pub fn get_hash_of(data: &impl AsRef<[u8]>) -> MerkleHash {
+        let mut hasher = Sha3_256::new();
+        hasher.update(data);
+        let result = hasher.finalize();
+        MerkleTree::bytes_to_hex(&result)
+    }
+
+    /// Converts a byte slice to a hexadecimal string.
+    pub(crate) fn bytes_to_hex(bytes: &[u8]) -> String {
+        let hex_chars: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        hex_chars.join("")
+    }
+
+    /// Converts a digest to its hash, a hexadecimal string.
+    pub(crate) fn digest_to_hex(digest: &MerkleDigest) -> MerkleHash {
+        MerkleTree::bytes_to_hex(digest)
+    }
+
+    /// Converts a hash, a hexadecimal string of 32 bytes, to its digest.
+    /// Any other string is rejected, including hashes with uppercase digits, as leaves are hashed as they are written and every hash of the tree is written in lowercase.
+    pub(crate) fn hex_to_digest(hash: &str) -> Result<MerkleDigest, MerkleTreeError> {
+        let invalid = || MerkleTreeError::InvalidHash(format!("Invalid hash: {:?}", hash));
+
+        if hash.len() != 64 || !hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+            return Err(invalid());
+        }
+
+        let mut digest = [0u8; 32];
+        for (i, byte) in digest.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hash[2 * i..2 * i + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(digest)
+    }
+
+    /// Returns the number of levels of a tree with the given number of leaves, including the leaves and the root.
+    pub(crate) fn height_of(len: usize) -> usize {
+        match len {
+            0 => 0,
+            n => (usize::BITS - (n - 1).leading_zeros()) as usize + 1,
+        }
+    }
+}
+
+impl<S: NodeStore> MerkleTree<S> {
+    /// Creates a MerkleTree from the nodes already kept in a store. An empty store creates an empty tree, that can be filled with `append_hashes`.
+    pub fn from_store(store: S) -> MerkleTree<S> {
+        MerkleTree {
+            store,
+            checkpoints: vec![],
+        }
+    }
+
+    /// Returns the store that keeps the nodes of the tree.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Consumes the tree, returning its store.
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Makes sure every node is persisted by the store.
+    pub fn flush(&mut self) -> Result<(), MerkleTreeError> {
+        self.store.flush()
+    }
+
+    /// Appends a list of hashes as leaves, in batches, and then updates the nodes to their right.
+    /// Unlike `add_hash` it doesn't check for duplicates, so it is the way to build big trees, even in stores that don't fit in memory.
+    /// If a hash is invalid, the leaves written before it are removed and the tree is left as it was.
+    pub fn append_hashes(
+        &mut self,
+        hashes: impl IntoIterator<Item = MerkleHash>,
+    ) -> Result<(), MerkleTreeError> {
+        let first_new = self.len();
+        if let Err(e) = self.write_hashes(hashes) {
+            self.truncate(first_new)?;
+            return Err(e);
+        }
+        self.rebuild_from(first_new)
+    }
+
+    /// Writes hashes after the last leaf in batches of `BUILD_CHUNK`, without updating the upper levels.
+    fn write_hashes(
+        &mut self,
+        hashes: impl IntoIterator<Item = MerkleHash>,
+    ) -> Result<(), MerkleTreeError> {
+        let mut digests = hashes
+            .into_iter()
+            .map(|hash| MerkleTree::hex_to_digest(&hash));
+
+        loop {
+            let chunk = digests
+                .by_ref()
+                .take(BUILD_CHUNK)
+                .collect::<Result<Vec<_>, _>>()?;
+            if chunk.is_empty() {
+                return Ok(());
+            }
+            self.write_leaves(&chunk)?;
+        }
+    }
+
+    /// Appends a list of leaf digests and updates the nodes to their right.
+    pub(crate) fn append_digests(
+        &mut self,
+        leaves: Vec<MerkleDigest>,
+    ) -> Result<(), MerkleTreeError> {
+        let first_new = self.len();
+        self.write_leaves(&leaves)?;
+        self.rebuild_from(first_new)
+    }
+
+    /// Replaces some leaves with new hashes, recomputing only the nodes in their paths to the root.
+    ///
+    /// # Parameters
+    /// - `updates`: The index of every leaf to replace and its new hash
+    pub fn update_leaves(
+        &mut self,
+        updates: Vec<(usize, MerkleHash)>,
+    ) -> Result<(), MerkleTreeError> {
+        let updates = updates
+            .into_iter()
+            .map(|(index, hash)| Ok((index, MerkleTree::hex_to_digest(&hash)?)))
+            .collect::<Result<Vec<_>, MerkleTreeError>>()?;
+        self.update_digests(&updates)
+    }
 
-        MerkleTree::new_from_hashes(hashes)
+    /// Replaces some leaf digests, recomputing only the nodes in their paths to the root.
+    pub(crate) fn update_digests(
+        &mut self,
+        updates: &[(usize, MerkleDigest)],
+    ) -> Result<(), MerkleTreeError> {
+        if let Some((index, _)) = updates.iter().find(|(index, _)| *index >= self.len()) {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "There is no leaf at index {}",
+                index
+            )));
+        }
+        self.record_overwritten(updates)?;
+        self.write_digests(updates)
     }
 
-    /// Recursive function that builds the Merkle Tree from a list of hashes.
-    fn build_tree(
-        tree: &mut MerkleTree,
-        mut hashes: Vec<MerkleHash>,
+    /// Writes some leaf digests and recomputes the nodes in their paths to the root, without saving the replaced leaves in the checkpoints.
+    pub(crate) fn write_digests(
+        &mut self,
+        updates: &[(usize, MerkleDigest)],
     ) -> Result<(), MerkleTreeError> {
-        if hashes.len() == 1 {
-            tree.levels.push(hashes);
+        let mut dirty = vec![];
+        for (index, digest) in updates {
+            self.store.put(0, *index, *digest)?;
+            dirty.push(*index);
+        }
+        dirty.sort_unstable();
+
+        for level in 1..self.height() {
+            dirty = dirty.iter().map(|index| index / 2).collect();
+            dirty.dedup();
+            for &index in dirty.iter() {
+                let left = self.store.get(level - 1, 2 * index)?;
+                let right = match 2 * index + 1 < self.level_len(level - 1) {
+                    true => self.store.get(level - 1, 2 * index + 1)?,
+                    false => left,
+                };
+                self.store
+                    .put(level, index, MerkleTree::combine(&left, &right))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops every leaf from `len` onwards and recomputes the right edge of the tree, whose nodes covered the dropped leaves.
+    pub(crate) fn truncate(&mut self, len: usize) -> Result<(), MerkleTreeError> {
+        if len >= self.len() {
             return Ok(());
         }
+        self.checkpoints
+            .retain(|checkpoint| checkpoint.size() <= len);
+        self.store.set_leaf_count(len)?;
+        self.rebuild_from(len.saturating_sub(1))
+    }
 
-        if hashes.len() % 2 != 0 {
-            let last = match hashes.last() {
-                Some(last) => last.clone(),
-                None => {
-                    return Err(MerkleTreeError::FailedToBuild(
-                        "No last element in hashes".to_string(),
-                    ));
-                }
-            };
-            hashes.push(last);
+    /// Writes leaves after the last one, without updating the upper levels.
+    fn write_leaves(&mut self, leaves: &[MerkleDigest]) -> Result<(), MerkleTreeError> {
+        let start = self.len();
+        self.store.set_leaf_count(start + leaves.len())?;
+        self.store.put_batch(0, start, leaves)
+    }
+
+    /// Recomputes every node that depends on the leaves from `first_leaf` onwards, level by level.
+    fn rebuild_from(&mut self, first_leaf: usize) -> Result<(), MerkleTreeError> {
+        for level in 1..self.height() {
+            self.rebuild_level(level, first_leaf >> level)?;
+        }
+        Ok(())
+    }
+
+    /// Recomputes the nodes of a level from `start` onwards from the nodes of the level below.
+    /// The nodes are processed in chunks of `BUILD_CHUNK`, and big chunks are hashed across several threads.
+    fn rebuild_level(&mut self, level: usize, mut start: usize) -> Result<(), MerkleTreeError> {
+        let parents_len = self.level_len(level);
+        let children_len = self.level_len(level - 1);
+
+        while start < parents_len {
+            let end = (start + BUILD_CHUNK).min(parents_len);
+            let children_end = (2 * end).min(children_len);
+            let children = self
+                .store
+                .get_batch(level - 1, 2 * start, children_end - 2 * start)?;
+
+            let mut parents = vec![[0u8; 32]; end - start];
+            MerkleTree::parallel_fill(&mut parents, |i| {
+                let left = &children[2 * i];
+                let right = children.get(2 * i + 1).unwrap_or(left);
+                MerkleTree::combine(left, right)
+            });
+
+            self.store.put_batch(level, start, &parents)?;
+            start = end;
         }
+        Ok(())
+    }
 
-        let len = hashes.len();
-        tree.levels.push(hashes.clone());
+    /// Appends the leaves of another tree after the leaves of this one, with the same root as a tree built from both lists of leaves.
+    ///
+    /// The nodes of this tree are kept, and so are the complete subtrees of the other tree whose leaves are aligned in the merged tree, which are the ones of 2^level leaves when the length of this tree is a multiple of 2^level. Only the nodes to their right are hashed.
+    ///
+    /// # Parameters
+    /// - `other`: The tree whose leaves go after the leaves of this one
+    pub fn merge<T: NodeStore>(&mut self, other: &MerkleTree<T>) -> Result<(), MerkleTreeError> {
+        let offset = self.len();
+        let mut leaf = 0;
+        while leaf < other.len() {
+            let len = BUILD_CHUNK.min(other.len() - leaf);
+            self.write_leaves(&other.store.get_batch(0, leaf, len)?)?;
+            leaf += len;
+        }
 
-        let mut next_hashes = vec![];
-        for i in (0..len).step_by(2) {
-            let left = &hashes[i];
-            let right = &hashes[i + 1];
+        for level in 1..self.height() {
+            if !offset.is_multiple_of(1 << level) {
+                self.rebuild_level(level, offset >> level)?;
+                continue;
+            }
 
-            next_hashes.push(MerkleTree::combine_hashes(left, right));
+            // Every node of the other tree that covers 2^level of its leaves is already a node of the merged tree.
+            let complete = other.len() >> level;
+            let mut copied = 0;
+            while copied < complete {
+                let len = BUILD_CHUNK.min(complete - copied);
+                let nodes = other.store.get_batch(level, copied, len)?;
+                self.store
+                    .put_batch(level, (offset >> level) + copied, &nodes)?;
+                copied += len;
+            }
+            self.rebuild_level(level, (offset >> level) + complete)?;
         }
+        Ok(())
+    }
 
-        MerkleTree::build_tree(tree, next_hashes)
+    /// Returns the number of levels of the tree, including the leaves and the root.
+    pub fn height(&self) -> usize {
+        MerkleTree::height_of(self.len())
     }
 
-    /// Concatenates two hashes and returns the hash of the concatenation.
-    // fn combine_hashes(mut left: MerkleHash, right: &MerkleHash) -> MerkleHash {
-    //     left = left + right;
+    /// Returns the number of leaves of the tree.
+    pub fn len(&self) -> usize {
+        self.store.leaf_count()
+    }
 
-    //     let mut hasher = Sha3_256::new();
-    //     hasher.update(left);
-    //     let result = hasher.finalize();
+    /// Returns true if the tree has no leaves.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 
-    //     MerkleTree::bytes_to_hex(&result)
-    // }
-    fn combine_hashes(left: &MerkleHash, right: &MerkleHash) -> MerkleHash {
-        let mut combined = left.clone();
-        combined.push_str(right);
+    /// Returns the number of nodes in the given level, 0 being the level of the leaves.
+    pub fn level_len(&self, level: usize) -> usize {
+        if level >= self.height() {
+            return 0;
+        }
+        self.len().div_ceil(1 << level)
+    }
 
-        let mut hasher = Sha3_256::new();
-        hasher.update(combined);
-        let result = hasher.finalize();
+    /// Returns the digest of the node at the given level and index.
+    pub(crate) fn digest(
+        &self,
+        level: usize,
+        index: usize,
+    ) -> Result<MerkleDigest, MerkleTreeError> {
+        if index >= self.level_len(level) {
+            return Err(MerkleTreeError::InvalidHash(format!(
+                "There is no node at level {} and index {}",
+                level, index
+            )));
+        }
+        self.store.get(level, index)
+    }
 
-        MerkleTree::bytes_to_hex(&result)
+    /// Returns the hash of the node at the given level and index, if it exists and can be read. Level 0 is the level of the leaves.
+    pub fn node(&self, level: usize, index: usize) -> Option<MerkleHash> {
+        self.digest(level, index)
+            .ok()
+            .map(|digest| MerkleTree::digest_to_hex(&digest))
     }
 
     /// Returns the root of the Merkle Tree, which is the Merkle Root.
-    fn root(&self) -> &MerkleHash {
-        &self.levels[self.levels.len() - 1][0]
+    pub fn root(&self) -> Result<MerkleHash, MerkleTreeError> {
+        self.root_digest()
+            .map(|digest| MerkleTree::digest_to_hex(&digest))
+    }
+
+    /// Returns the digest of the root of the Merkle Tree.
+    pub(crate) fn root_digest(&self) -> Result<MerkleDigest, MerkleTreeError> {
+        if self.is_empty() {
+            return Err(MerkleTreeError::InvalidHash(
+                "The tree has no leaves".to_string(),
+            ));
+        }
+        self.digest(self.height() - 1, 0)
+    }
+
+    /// Returns the index of a leaf, if it is in the tree.
+    pub(crate) fn position_of(&self, leaf: &MerkleHash) -> Option<usize> {
+        let digest = MerkleTree::hex_to_digest(leaf).ok()?;
+
+        let mut start = 0;
+        while start < self.len() {
+            let len = BUILD_CHUNK.min(self.len() - start);
+            let leaves = self.store.get_batch(0, start, len).ok()?;
+            if let Some(i) = leaves.iter().position(|leaf| *leaf == digest) {
+                return Some(start + i);
+            }
+            start += len;
+        }
+        None
     }
 
     /// Verifies that a given hash is contained in the Merkle Tree, in O(log n) time, with n = number of leaf hashes.
@@ -110,10 +473,6 @@ impl MerkleTree {
     /// - `leaf`: The hash to verify
     /// - `index`: The index of the hash in the bottom level of the tree
     pub fn verify_with_index(&self, leaf: &MerkleHash, index: u32) -> bool {
-        if self.levels[0][index as usize] != *leaf {
-            return false;
-        }
-
         let proof = match self.proof_of_inclusion_with_index(leaf, index) {
             Ok(proof) => proof,
             Err(_) => return false,
@@ -128,7 +487,7 @@ impl MerkleTree {
             };
         }
 
-        &computed_root == self.root()
+        self.root().is_ok_and(|root| computed_root == root)
     }
 
     /// Verifies that a given hash is contained in the Merkle Tree, in O(n) time, with n = number of leaf hashes.
@@ -136,7 +495,7 @@ impl MerkleTree {
     /// # Parameters
     /// - `leaf`: The hash to verify
     pub fn verify(&self, leaf: &MerkleHash) -> bool {
-        let hash_index = match self.levels[0].iter().position(|h| h == leaf) {
+        let hash_index = match self.position_of(leaf) {
             Some(index) => index,
             None => return false,
         };
@@ -144,17 +503,6 @@ impl MerkleTree {
         self.verify_with_index(leaf, hash_index as u32)
     }
 
-    /// Returns the hash of the given data
-    ///
-    /// # Parameters
-    /// - `data`: An object that can be converted to a byte slice
-    pub fn get_hash_of(data: &impl AsRef<[u8]>) -> MerkleHash {
-        let mut hasher = Sha3_256::new();
-        hasher.update(data);
-        let result = hasher.finalize();
-        MerkleTree::bytes_to_hex(&result)
-    }
-
     /// Returns a proof of inclusion for a given hash in the Merkle Tree. The proof generated conains the hashes of the siblings of the nodes in the path from the leaf to the root, and their directions. In O(log n) time, with n = number of leaf hashes..
     ///
     /// # Parameters
@@ -166,29 +514,31 @@ impl MerkleTree {
     pub fn proof_of_inclusion_with_index(
         &self,
         leaf: &MerkleHash,
-        mut index: u32,
+        index: u32,
     ) -> Result<ProofOfInclusion, MerkleTreeError> {
-        if self.levels[0][index as usize] != *leaf {
-            return Err(MerkleTreeError::InvalidHash(
-                "Hash is not part of the tree".to_string(),
-            ));
+        let mut index = index as usize;
+
+        match (self.digest(0, index), MerkleTree::hex_to_digest(leaf)) {
+            (Ok(stored), Ok(digest)) if stored == digest => (),
+            _ => {
+                return Err(MerkleTreeError::InvalidHash(
+                    "Hash is not part of the tree".to_string(),
+                ))
+            }
         }
 
         let mut proof = vec![];
 
-        for level in self.levels.iter() {
-            if level.len() == 1 {
-                break;
-            }
-
-            if index % 2 == 0 {
-                if index + 1 < level.len() as u32 {
-                    proof.push((level[(index + 1) as usize].clone(), Direction::Right));
-                } else {
-                    proof.push((level[index as usize].clone(), Direction::Right));
-                }
+        for level in 0..self.height() - 1 {
+            if index.is_multiple_of(2) {
+                let sibling = match index + 1 < self.level_len(level) {
+                    true => self.digest(level, index + 1)?,
+                    false => self.digest(level, index)?,
+                };
+                proof.push((MerkleTree::digest_to_hex(&sibling), Direction::Right));
             } else {
-                proof.push((level[(index - 1) as usize].clone(), Direction::Left));
+                let sibling = self.digest(level, index - 1)?;
+                proof.push((MerkleTree::digest_to_hex(&sibling), Direction::Left));
             }
 
             index /= 2;
@@ -208,7 +558,7 @@ impl MerkleTree {
         &self,
         leaf: &MerkleHash,
     ) -> Result<ProofOfInclusion, MerkleTreeError> {
-        let hash_index = match self.levels[0].iter().position(|h| h == leaf) {
+        let hash_index = match self.position_of(leaf) {
             Some(index) => index,
             None => {
                 return Err(MerkleTreeError::InvalidHash(
@@ -220,31 +570,109 @@ impl MerkleTree {
         self.proof_of_inclusion_with_index(leaf, hash_index as u32)
     }
 
+    /// Returns the last node of every level of the tree with the first `size` leaves, from the leaves to the root. Those are the only nodes of the smaller tree that can differ from the nodes of this one, as every other node is the root of a complete subtree of the first `size` leaves.
+    fn right_edge_at(&self, size: usize) -> Result<Vec<MerkleDigest>, MerkleTreeError> {
+        if size == 0 || size > self.len() {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "The tree has {} leaves, it can't be seen with {}",
+                self.len(),
+                size
+            )));
+        }
+
+        let mut edge = vec![self.digest(0, size - 1)?];
+        let mut len = size;
+        let mut level = 0;
+        while len > 1 {
+            let last = edge[level];
+            let child = |index: usize| match index == len - 1 {
+                true => Ok(last),
+                false => self.digest(level, index),
+            };
+
+            let index = len.div_ceil(2) - 1;
+            let left = child(2 * index)?;
+            let right = match 2 * index + 1 < len {
+                true => child(2 * index + 1)?,
+                false => left,
+            };
+            edge.push(MerkleTree::combine(&left, &right));
+            len = len.div_ceil(2);
+            level += 1;
+        }
+        Ok(edge)
+    }
+
+    /// Returns the root the tree had when it had the first `size` leaves, recomputing only its right edge, in O(log n) time.
+    ///
+    /// # Parameters
+    /// - `size`: The number of leaves of the older tree, between 1 and the number of leaves of this one
+    pub fn root_at(&self, size: usize) -> Result<MerkleHash, MerkleTreeError> {
+        let edge = self.right_edge_at(size)?;
+        Ok(MerkleTree::digest_to_hex(&edge[edge.len() - 1]))
+    }
+
+    /// Returns the proof of inclusion of a leaf in the tree with the first `size` leaves, which leads to `root_at(size)`, in O(log n) time.
+    ///
+    /// # Parameters
+    /// - `index`: The index of the leaf, smaller than `size`
+    /// - `size`: The number of leaves of the older tree, between 1 and the number of leaves of this one
+    pub fn proof_of_inclusion_at(
+        &self,
+        index: usize,
+        size: usize,
+    ) -> Result<ProofOfInclusion, MerkleTreeError> {
+        let edge = self.right_edge_at(size)?;
+        if index >= size {
+            return Err(MerkleTreeError::InvalidHash(format!(
+                "There is no leaf at index {} in a tree of {} leaves",
+                index, size
+            )));
+        }
+
+        let leaf = MerkleTree::digest_to_hex(&self.digest(0, index)?);
+        let mut proof = vec![];
+        let mut index = index;
+        let mut len = size;
+        for (level, last) in edge.iter().enumerate().take(edge.len() - 1) {
+            let node = |index: usize| match index == len - 1 {
+                true => Ok(*last),
+                false => self.digest(level, index),
+            };
+
+            if index.is_multiple_of(2) {
+                let sibling = match index + 1 < len {
+                    true => node(index + 1)?,
+                    false => node(index)?,
+                };
+                proof.push((MerkleTree::digest_to_hex(&sibling), Direction::Right));
+            } else {
+                proof.push((
+                    MerkleTree::digest_to_hex(&node(index - 1)?),
+                    Direction::Left,
+                ));
+            }
+            index /= 2;
+            len = len.div_ceil(2);
+        }
+
+        Ok(ProofOfInclusion::new_from(leaf, proof))
+    }
+
     /// Adds a hash to the Merkle Tree, updating the tree structure.
+    /// Only the nodes in the path from the new leaf to the root are recomputed, in O(log n) time, besides the check for duplicates.
     ///
     /// # Parameters
     /// - `hash`: The hash to add to the tree
     pub fn add_hash(&mut self, hash: MerkleHash) -> Result<(), MerkleTreeError> {
-        let len = self.levels[0].len();
-
         if self.verify(&hash) {
             return Err(MerkleTreeError::HashAlreadyExists(
                 "Hash is already contained in the tree".to_string(),
             ));
         }
 
-        if len >= 2 && self.levels[0][len - 1] == self.levels[0][len - 2] {
-            self.levels[0][len - 1] = hash;
-        } else {
-            self.levels[0].push(hash);
-        }
-
-        let mut new_tree = MerkleTree { levels: vec![] };
-
-        MerkleTree::build_tree(&mut new_tree, self.levels[0].clone())?;
-
-        self.levels = new_tree.levels;
-        Ok(())
+        let digest = MerkleTree::hex_to_digest(&hash)?;
+        self.append_digests(vec![digest])
     }
 
     /// Adds an element that will be hashed before adding it to the Merkle Tree, .
@@ -253,28 +681,35 @@ impl MerkleTree {
         self.add_hash(hash)
     }
 
-    /// Converts a byte slice to a hexadecimal string.
-    fn bytes_to_hex(bytes: &[u8]) -> String {
-        let hex_chars: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
-        hex_chars.join("")
-    }
-
     /// Prints the Merkle Tree structure.
     pub fn print(&self) {
-        for i in (0..self.levels.len()).rev() {
-            println!("LEVEL {}:", self.levels.len() - i - 1);
-            for hash in self.levels[i].iter() {
-                println!("- {:?}", hash);
+        let height = self.height();
+        for level in (0..height).rev() {
+            println!("LEVEL {}:", height - level - 1);
+            for index in 0..self.level_len(level) {
+                match self.node(level, index) {
+                    Some(hash) => println!("- {:?}", hash),
+                    None => println!("- <unreadable node>"),
+                }
             }
         }
     }
 }
 
+/// Returns the hashes of `<prefix>0` to `<prefix><len - 1>`, used as leaves by the tests.
+#[cfg(test)]
+pub(crate) fn test_hashes(prefix: &str, len: usize) -> Vec<MerkleHash> {
+    (0..len)
+        .map(|i| MerkleTree::get_hash_of(&format!("{}{}", prefix, i)))
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
     use sha3::{Digest, Sha3_256};
 
-    use crate::merkle_tree::MerkleTree;
+    use crate::merkle_hash::MerkleHash;
+    use crate::merkle_tree::{MerkleTree, BUILD_CHUNK, PARALLEL_THRESHOLD};
 
     #[test]
     fn build_simple_tree() {
@@ -282,9 +717,7 @@ mod test {
 
         let tree = MerkleTree::new_from_hashables(data).unwrap();
 
-        println!("LEVEL 1: {:?}", tree.levels[0]);
-        println!("LEVEL 2: {:?}", tree.levels[1]);
-        println!("LEVEL 3: {:?}", tree.levels[2]);
+        tree.print();
 
         let mut hasher = Sha3_256::new();
         hasher.update([1; 32]);
@@ -293,10 +726,10 @@ mod test {
         let hash: [u8; 32] = result.into();
         println!("HASH 1: {:?}", hash);
 
-        assert_eq!(tree.levels.len(), 3);
-        assert_eq!(tree.levels[0].len(), 4);
-        assert_eq!(tree.levels[1].len(), 2);
-        assert_eq!(tree.levels[2].len(), 1);
+        assert_eq!(tree.height(), 3);
+        assert_eq!(tree.level_len(0), 4);
+        assert_eq!(tree.level_len(1), 2);
+        assert_eq!(tree.level_len(2), 1);
     }
 
     #[test]
@@ -307,10 +740,10 @@ mod test {
 
         tree.print();
 
-        assert_eq!(tree.levels.len(), 3);
-        assert_eq!(tree.levels[0].len(), 4);
-        assert_eq!(tree.levels[1].len(), 2);
-        assert_eq!(tree.levels[2].len(), 1);
+        assert_eq!(tree.height(), 3);
+        assert_eq!(tree.level_len(0), 4);
+        assert_eq!(tree.level_len(1), 2);
+        assert_eq!(tree.level_len(2), 1);
     }
 
     #[test]
@@ -507,4 +940,165 @@ mod test {
         println!("TREE AFTER ADDING:");
         tree.print()
     }
+
+    #[test]
+    fn parallel_build_matches_sequential_build() {
+        let data: Vec<String> = (0..3 * PARALLEL_THRESHOLD + 5)
+            .map(|i| format!("something{}", i))
+            .collect();
+
+        let tree = MerkleTree::new_from_hashables(data.clone()).unwrap();
+
+        let mut level: Vec<MerkleHash> = data.iter().map(MerkleTree::get_hash_of).collect();
+        while level.len() > 1 {
+            if !level.len().is_multiple_of(2) {
+                level.push(level[level.len() - 1].clone());
+            }
+            level = level
+                .chunks(2)
+                .map(|pair| MerkleTree::combine_hashes(&pair[0], &pair[1]))
+                .collect();
+        }
+
+        assert_eq!(tree.root().unwrap(), level[0]);
+        assert!(tree.verify_with_index(&MerkleTree::get_hash_of(&"something5000"), 5000));
+    }
+
+    #[test]
+    fn adding_hashes_matches_building_from_scratch() {
+        let hashes: Vec<MerkleHash> = (0..19)
+            .map(|i| MerkleTree::get_hash_of(&format!("something{}", i)))
+            .collect();
+
+        let mut tree = MerkleTree::new_from_hashes(hashes[..1].to_vec()).unwrap();
+        for size in 2..=hashes.len() {
+            tree.add_hash(hashes[size - 1].clone()).unwrap();
+
+            let expected = MerkleTree::new_from_hashes(hashes[..size].to_vec()).unwrap();
+            assert_eq!(
+                tree.root().unwrap(),
+                expected.root().unwrap(),
+                "size {}",
+                size
+            );
+            assert_eq!(tree.height(), expected.height());
+        }
+
+        assert_eq!(tree.node(0, 18), Some(hashes[18].clone()));
+        assert_eq!(tree.node(0, 19), None);
+        assert_eq!(tree.node(tree.height() - 1, 0), tree.root().ok());
+    }
+
+    #[test]
+    fn updating_leaves_matches_building_from_scratch() {
+        let mut hashes: Vec<MerkleHash> = (0..37)
+            .map(|i| MerkleTree::get_hash_of(&format!("something{}", i)))
+            .collect();
+        let mut tree = MerkleTree::new_from_hashes(hashes.clone()).unwrap();
+
+        let updates: Vec<(usize, MerkleHash)> = [0, 17, 18, 36]
+            .iter()
+            .map(|&i| (i, MerkleTree::get_hash_of(&format!("updated{}", i))))
+            .collect();
+        for (i, hash) in updates.iter() {
+            hashes[*i] = hash.clone();
+        }
+        tree.update_leaves(updates).unwrap();
+
+        let expected = MerkleTree::new_from_hashes(hashes).unwrap();
+        assert_eq!(tree.root().unwrap(), expected.root().unwrap());
+        assert!(tree
+            .update_leaves(vec![(37, MerkleTree::get_hash_of(&"x"))])
+            .is_err());
+    }
+
+    #[test]
+    fn older_roots_and_proofs_are_recomputed() {
+        let hashes: Vec<MerkleHash> = (0..45)
+            .map(|i| MerkleTree::get_hash_of(&format!("leaf{}", i)))
+            .collect();
+        let tree = MerkleTree::new_from_hashes(hashes.clone()).unwrap();
+
+        for size in 1..=45 {
+            let old = MerkleTree::new_from_hashes(hashes[..size].to_vec()).unwrap();
+            let root = tree.root_at(size).unwrap();
+            assert_eq!(root, old.root().unwrap());
+
+            for (index, hash) in hashes[..size].iter().enumerate() {
+                let proof = tree.proof_of_inclusion_at(index, size).unwrap();
+                let expected = old
+                    .proof_of_inclusion_with_index(hash, index as u32)
+                    .unwrap();
+                assert_eq!(proof.leaf(), hash);
+                assert!(proof.iter().eq(expected.iter()));
+                assert!(proof.verify(&root));
+            }
+        }
+        assert!(tree.root_at(0).is_err());
+        assert!(tree.root_at(46).is_err());
+        assert!(tree.proof_of_inclusion_at(10, 10).is_err());
+    }
+
+    #[test]
+    fn merged_trees_have_the_root_of_the_combined_leaves() {
+        let hashes: Vec<MerkleHash> = (0..70)
+            .map(|i| MerkleTree::get_hash_of(&format!("leaf{}", i)))
+            .collect();
+
+        for split in 1..hashes.len() {
+            let mut tree = MerkleTree::new_from_hashes(hashes[..split].to_vec()).unwrap();
+            let other = MerkleTree::new_from_hashes(hashes[split..].to_vec()).unwrap();
+            tree.merge(&other).unwrap();
+
+            let expected = MerkleTree::new_from_hashes(hashes.clone()).unwrap();
+            assert_eq!(tree.len(), hashes.len());
+            assert_eq!(
+                tree.root().unwrap(),
+                expected.root().unwrap(),
+                "split {}",
+                split
+            );
+            for level in 0..tree.height() {
+                for index in 0..tree.level_len(level) {
+                    assert_eq!(tree.node(level, index), expected.node(level, index));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn failed_appends_leave_the_tree_unchanged() {
+        let hashes: Vec<MerkleHash> = (0..10)
+            .map(|i| MerkleTree::get_hash_of(&format!("leaf{}", i)))
+            .collect();
+        let mut tree = MerkleTree::new_from_hashes(hashes.clone()).unwrap();
+        let root = tree.root().unwrap();
+
+        let appended = std::iter::repeat_n(hashes[0].clone(), BUILD_CHUNK + 5)
+            .chain(std::iter::once("not a hash".to_string()));
+        assert!(tree.append_hashes(appended).is_err());
+        assert_eq!(tree.len(), 10);
+        assert_eq!(tree.root().unwrap(), root);
+
+        tree.append_hashes(hashes.clone()).unwrap();
+        let expected = MerkleTree::new_from_hashes([hashes.clone(), hashes].concat()).unwrap();
+        assert_eq!(tree.root().unwrap(), expected.root().unwrap());
+    }
+
+    #[test]
+    fn invalid_hashes_are_rejected() {
+        assert!(MerkleTree::new_from_hashes(vec![]).is_err());
+        assert!(MerkleTree::new_from_hashes(vec!["not a hash".to_string()]).is_err());
+
+        let mut tree = MerkleTree::new_from_hashables(vec!["something00"]).unwrap();
+        assert!(tree.add_hash("zz".repeat(32)).is_err());
+        assert!(!tree.verify_with_index(&MerkleTree::get_hash_of(&"something00"), 7));
+
+        // Leaves are hashed as they are written, so an uppercase copy of a leaf would be a different leaf with the same digest.
+        let uppercase = MerkleTree::get_hash_of(&"something00").to_uppercase();
+        assert!(MerkleTree::new_from_hashes(vec![uppercase.clone()]).is_err());
+        assert!(tree.add_hash(uppercase.clone()).is_err());
+        assert!(!tree.verify(&uppercase));
+        assert_eq!(tree.len(), 1);
+    }
 }
@@ -9,4 +9,12 @@ pub enum MerkleTreeError {
     HashAlreadyExists(String),
     /// Failed to process the elements file.
     FailedToProcessFile(String),
+    /// Failed to write an output file.
+    FailedToWriteFile(String),
+    /// A value could not be parsed or encoded.
+    InvalidValue(String),
+    /// Failed to read or write the nodes of the tree.
+    StorageError(String),
+    /// A signature doesn't match the signed data or the key.
+    InvalidSignature(String),
 }
@@ -0,0 +1,99 @@
+use crate::merkle_hash::MerkleDigest;
+use crate::merkle_tree_error::MerkleTreeError;
+
+/// The `NodeStore` trait abstracts where the nodes of a `MerkleTree` are kept.
+///
+/// Nodes are addressed by their position: the level, 0 being the level of the leaves, and the index inside the level. The store also keeps the number of leaves, from which the number of nodes of every level is derived, and must have room for all of them.
+pub trait NodeStore {
+    /// Returns the number of leaves in the store.
+    fn leaf_count(&self) -> usize;
+
+    /// Sets the number of leaves, making room for the nodes of the new size when it grows and discarding the nodes that are left out when it shrinks.
+    fn set_leaf_count(&mut self, leaf_count: usize) -> Result<(), MerkleTreeError>;
+
+    /// Returns the node at the given position.
+    fn get(&self, level: usize, index: usize) -> Result<MerkleDigest, MerkleTreeError>;
+
+    /// Stores the node at the given position.
+    fn put(
+        &mut self,
+        level: usize,
+        index: usize,
+        digest: MerkleDigest,
+    ) -> Result<(), MerkleTreeError>;
+
+    /// Returns `len` consecutive nodes of a level, starting from `start`.
+    fn get_batch(
+        &self,
+        level: usize,
+        start: usize,
+        len: usize,
+    ) -> Result<Vec<MerkleDigest>, MerkleTreeError> {
+        (start..start + len)
+            .map(|index| self.get(level, index))
+            .collect()
+    }
+
+    /// Stores consecutive nodes of a level, starting from `start`.
+    fn put_batch(
+        &mut self,
+        level: usize,
+        start: usize,
+        digests: &[MerkleDigest],
+    ) -> Result<(), MerkleTreeError> {
+        for (i, digest) in digests.iter().enumerate() {
+            self.put(level, start + i, *digest)?;
+        }
+        Ok(())
+    }
+
+    /// Makes sure every write is persisted. Stores that live in memory don't need to do anything.
+    fn flush(&mut self) -> Result<(), MerkleTreeError> {
+        Ok(())
+    }
+}
+
+impl<S: NodeStore + ?Sized> NodeStore for Box<S> {
+    fn leaf_count(&self) -> usize {
+        (**self).leaf_count()
+    }
+
+    fn set_leaf_count(&mut self, leaf_count: usize) -> Result<(), MerkleTreeError> {
+        (**self).set_leaf_count(leaf_count)
+    }
+
+    fn get(&self, level: usize, index: usize) -> Result<MerkleDigest, MerkleTreeError> {
+        (**self).get(level, index)
+    }
+
+    fn put(
+        &mut self,
+        level: usize,
+        index: usize,
+        digest: MerkleDigest,
+    ) -> Result<(), MerkleTreeError> {
+        (**self).put(level, index, digest)
+    }
+
+    fn get_batch(
+        &self,
+        level: usize,
+        start: usize,
+        len: usize,
+    ) -> Result<Vec<MerkleDigest>, MerkleTreeError> {
+        (**self).get_batch(level, start, len)
+    }
+
+    fn put_batch(
+        &mut self,
+        level: usize,
+        start: usize,
+        digests: &[MerkleDigest],
+    ) -> Result<(), MerkleTreeError> {
+        (**self).put_batch(level, start, digests)
+    }
+
+    fn flush(&mut self) -> Result<(), MerkleTreeError> {
+        (**self).flush()
+    }
+}
@@ -1,6 +1,9 @@
-use crate::{direction::Direction, merkle_hash::MerkleHash};
+use serde::{Deserialize, Serialize};
+
+use crate::{direction::Direction, merkle_hash::MerkleHash, merkle_tree::MerkleTree};
 
 /// The `ProofOfInclusion` struct contains the proof of inclusion for a leaf in a Merkle Tree.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProofOfInclusion {
     proof: Vec<(MerkleHash, Direction)>,
     leaf: MerkleHash,
@@ -24,4 +27,37 @@ impl ProofOfInclusion {
     pub fn iter(&self) -> impl Iterator<Item = &(MerkleHash, Direction)> {
         self.proof.iter()
     }
+
+    /// Returns the leaf whose inclusion is proved.
+    pub fn leaf(&self) -> &MerkleHash {
+        &self.leaf
+    }
+
+    /// Returns the index of the leaf, read from the directions of the proof: at every level, the leaf is on the right when its sibling is on the left.
+    ///
+    /// The proof doesn't authenticate the index. The last node of a level with an odd number of nodes is hashed with itself, so a proof that puts a leaf in the place of that copy, past the last leaf, leads to the root too. A verifier that knows the size of the tree rejects those proofs by checking that the index is below it.
+    pub fn index(&self) -> usize {
+        self.proof
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, direction))| *direction == Direction::Left)
+            .map(|(level, _)| 1 << level)
+            .sum()
+    }
+
+    /// Returns the root obtained by hashing the leaf with every hash of the proof, in order.
+    pub fn root(&self) -> MerkleHash {
+        self.proof.iter().fold(
+            self.leaf.clone(),
+            |node, (sibling, direction)| match direction {
+                Direction::Left => MerkleTree::combine_hashes(sibling, &node),
+                Direction::Right => MerkleTree::combine_hashes(&node, sibling),
+            },
+        )
+    }
+
+    /// Verifies the proof against a root, without needing the tree.
+    pub fn verify(&self, root: &MerkleHash) -> bool {
+        self.root() == *root
+    }
 }
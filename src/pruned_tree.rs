@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+
+use crate::direction::Direction;
+use crate::merkle_hash::{MerkleDigest, MerkleHash};
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+use crate::proof_of_inclusion::ProofOfInclusion;
+
+/// A leaf of a `PrunedTree` whose proof of inclusion is kept.
+#[derive(Debug, Clone)]
+struct MarkedLeaf {
+    leaf: MerkleDigest,
+    /// The sibling at every level of the path to the root that is the root of a complete subtree. Left siblings are known when the leaf is appended, right ones are filled when their subtree is completed.
+    siblings: Vec<Option<MerkleDigest>>,
+}
+
+/// The `PrunedTree` struct is a Merkle Tree that only keeps what it needs to compute its root and the proofs of inclusion of the leaves marked when they were appended.
+///
+/// Like a `StreamingBuilder` it keeps the frontier: for every level, the root of the last complete subtree waiting for its right sibling. Every marked leaf also keeps the roots of the complete subtrees along its path, which are filled as the subtrees to its right are completed. The nodes of the right edge, which change with every append, are recomputed from the frontier. Memory is O(m log n), with m = number of marked leaves and n = number of leaves, and the roots and proofs are the same ones a `MerkleTree` with the same leaves has.
+///
+/// # Methods
+/// - `append`: Adds a hash as the next leaf.
+/// - `append_marked`: Adds a hash as the next leaf and keeps its proof of inclusion.
+/// - `unmark`: Stops keeping the proof of inclusion of a leaf.
+/// - `root`: Returns the root of the tree.
+/// - `proof_of_inclusion`: Returns the proof of inclusion of a marked leaf.
+#[derive(Debug, Default)]
+pub struct PrunedTree {
+    frontier: Vec<Option<MerkleDigest>>,
+    len: usize,
+    marked: BTreeMap<usize, MarkedLeaf>,
+}
+
+impl PrunedTree {
+    /// Creates an empty tree.
+    pub fn new() -> Self {
+        PrunedTree::default()
+    }
+
+    /// Returns the number of leaves of the tree.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the tree has no leaves.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the indices of the marked leaves, in order.
+    pub fn marked(&self) -> impl Iterator<Item = usize> + '_ {
+        self.marked.keys().copied()
+    }
+
+    /// Adds a hash as the next leaf, returning its index.
+    pub fn append(&mut self, hash: &MerkleHash) -> Result<usize, MerkleTreeError> {
+        let leaf = MerkleTree::hex_to_digest(hash)?;
+        Ok(self.push(leaf))
+    }
+
+    /// Adds a hash as the next leaf and keeps its proof of inclusion up to date from now on, returning its index.
+    pub fn append_marked(&mut self, hash: &MerkleHash) -> Result<usize, MerkleTreeError> {
+        let leaf = MerkleTree::hex_to_digest(hash)?;
+        let index = self.len;
+
+        // The left siblings of the new leaf are the peaks of the frontier.
+        let siblings = (0..self.frontier.len())
+            .map(|level| match (index >> level) % 2 {
+                1 => self.frontier[level],
+                _ => None,
+            })
+            .collect();
+        self.marked.insert(index, MarkedLeaf { leaf, siblings });
+        Ok(self.push(leaf))
+    }
+
+    /// Stops keeping the proof of inclusion of a leaf, returning false if it wasn't marked.
+    pub fn unmark(&mut self, index: usize) -> bool {
+        self.marked.remove(&index).is_some()
+    }
+
+    /// Adds a leaf, merging it with the complete subtrees to its left and handing every completed subtree that is a right sibling to the marked leaves of its left sibling.
+    fn push(&mut self, leaf: MerkleDigest) -> usize {
+        let index = self.len;
+        let mut node = leaf;
+        let mut level = 0;
+
+        loop {
+            let position = index >> level;
+            if position % 2 == 1 {
+                let start = (position - 1) << level;
+                for marked in self.marked.range_mut(start..position << level) {
+                    let siblings = &mut marked.1.siblings;
+                    if siblings.len() <= level {
+                        siblings.resize(level + 1, None);
+                    }
+                    siblings[level] = Some(node);
+                }
+            }
+
+            match self.frontier.get_mut(level).and_then(Option::take) {
+                Some(left) => {
+                    node = MerkleTree::combine(&left, &node);
+                    level += 1;
+                }
+                None => break,
+            }
+        }
+
+        if level == self.frontier.len() {
+            self.frontier.push(Some(node));
+        } else {
+            self.frontier[level] = Some(node);
+        }
+        self.len += 1;
+        index
+    }
+
+    /// Returns the number of levels of the tree, including the leaves and the root.
+    fn height(&self) -> usize {
+        MerkleTree::height_of(self.len)
+    }
+
+    /// Returns, for every level, the last node if it is the root of an incomplete subtree, computed from the frontier.
+    fn partial_nodes(&self) -> Vec<Option<MerkleDigest>> {
+        let mut partial: Vec<Option<MerkleDigest>> = vec![None];
+        for level in 1..self.height() {
+            let remaining = self.len % (1 << level);
+            let half = 1 << (level - 1);
+            let below = partial[level - 1];
+            let peak = self.frontier.get(level - 1).copied().flatten();
+
+            let node = match (remaining, peak, below) {
+                (0, _, _) => None,
+                (r, Some(peak), Some(below)) if r > half => {
+                    Some(MerkleTree::combine(&peak, &below))
+                }
+                (r, Some(peak), _) if r == half => Some(MerkleTree::combine(&peak, &peak)),
+                (_, _, Some(below)) => Some(MerkleTree::combine(&below, &below)),
+                _ => None,
+            };
+            partial.push(node);
+        }
+        partial
+    }
+
+    /// Returns the root of the tree, or `None` if it is empty.
+    pub fn root(&self) -> Option<MerkleHash> {
+        let top = self.height().checked_sub(1)?;
+        let root = match self.partial_nodes()[top] {
+            Some(partial) => partial,
+            None => self.frontier[top]?,
+        };
+        Some(MerkleTree::digest_to_hex(&root))
+    }
+
+    /// Returns the proof of inclusion of a marked leaf in the current tree.
+    pub fn proof_of_inclusion(&self, index: usize) -> Result<ProofOfInclusion, MerkleTreeError> {
+        let marked = self.marked.get(&index).ok_or_else(|| {
+            MerkleTreeError::InvalidHash(format!("The leaf at index {} is not marked", index))
+        })?;
+        let partial = self.partial_nodes();
+        let missing = || {
+            MerkleTreeError::InvalidHash(format!(
+                "The path of the leaf at index {} is incomplete",
+                index
+            ))
+        };
+
+        let mut node = marked.leaf;
+        let mut proof = vec![];
+        for (level, partial) in partial.iter().enumerate().take(self.height() - 1) {
+            let position = index >> level;
+            let level_len = self.len.div_ceil(1 << level);
+            let stored = marked.siblings.get(level).copied().flatten();
+
+            let (sibling, direction) = if position % 2 == 1 {
+                (stored.ok_or_else(missing)?, Direction::Left)
+            } else if position + 1 >= level_len {
+                (node, Direction::Right)
+            } else if (position + 2) << level <= self.len {
+                (stored.ok_or_else(missing)?, Direction::Right)
+            } else {
+                (partial.ok_or_else(missing)?, Direction::Right)
+            };
+
+            node = match direction {
+                Direction::Left => MerkleTree::combine(&sibling, &node),
+                Direction::Right => MerkleTree::combine(&node, &sibling),
+            };
+            proof.push((MerkleTree::digest_to_hex(&sibling), direction));
+        }
+
+        Ok(ProofOfInclusion::new_from(
+            MerkleTree::digest_to_hex(&marked.leaf),
+            proof,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::merkle_tree::MerkleTree;
+    use crate::pruned_tree::PrunedTree;
+
+    #[test]
+    fn roots_and_proofs_match_the_full_tree() {
+        let hashes: Vec<String> = (0..70)
+            .map(|i| MerkleTree::get_hash_of(&format!("leaf{}", i)))
+            .collect();
+        let marked = [0, 5, 6, 31, 32, 47, 69];
+        let mut pruned = PrunedTree::new();
+        assert_eq!(pruned.root(), None);
+
+        for (i, hash) in hashes.iter().enumerate() {
+            match marked.contains(&i) {
+                true => pruned.append_marked(hash).unwrap(),
+                false => pruned.append(hash).unwrap(),
+            };
+
+            let tree = MerkleTree::new_from_hashes(hashes[..=i].to_vec()).unwrap();
+            assert_eq!(pruned.root(), tree.root().ok(), "size {}", i + 1);
+            for index in pruned.marked() {
+                let proof = pruned.proof_of_inclusion(index).unwrap();
+                let expected = tree
+                    .proof_of_inclusion_with_index(&hashes[index], index as u32)
+                    .unwrap();
+                assert!(
+                    proof.iter().eq(expected.iter()),
+                    "leaf {} of {}",
+                    index,
+                    i + 1
+                );
+            }
+        }
+
+        assert!(pruned.proof_of_inclusion(7).is_err());
+        assert!(pruned.unmark(5));
+        assert!(pruned.proof_of_inclusion(5).is_err());
+        assert_eq!(
+            pruned.marked().collect::<Vec<_>>(),
+            vec![0, 6, 31, 32, 47, 69]
+        );
+    }
+
+    #[test]
+    fn memory_only_grows_with_the_marked_leaves() {
+        let mut pruned = PrunedTree::new();
+        pruned
+            .append_marked(&MerkleTree::get_hash_of(&"mine"))
+            .unwrap();
+        for i in 0..20_000 {
+            pruned
+                .append(&MerkleTree::get_hash_of(&format!("leaf{}", i)))
+                .unwrap();
+        }
+
+        assert!(pruned.frontier.len() <= pruned.height());
+        let marked = &pruned.marked[&0];
+        assert!(marked.siblings.len() < pruned.height());
+        let root = pruned.root().unwrap();
+        assert!(pruned.proof_of_inclusion(0).unwrap().verify(&root));
+    }
+}
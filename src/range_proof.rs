@@ -0,0 +1,172 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::merkle_hash::MerkleHash;
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+use crate::node_store::NodeStore;
+
+/// The `RangeProof` struct proves that a contiguous range of leaves is in a tree, with a single proof.
+///
+/// The nodes covering the range are computed from its leaves, so the proof only holds the siblings along its left and right boundaries: at every level, the node to the left of the first covered node if it is a right child, and the node to the right of the last one if it is a left child. That is at most 2 log n hashes, whatever the length of the range.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RangeProof {
+    pub size: usize,
+    pub start: usize,
+    pub leaves: Vec<MerkleHash>,
+    /// The boundary siblings, from the leaves to the root, the left one first at every level.
+    pub siblings: Vec<MerkleHash>,
+}
+
+impl<S: NodeStore> MerkleTree<S> {
+    /// Returns a proof that the leaves in the given range are in the tree.
+    ///
+    /// # Parameters
+    /// - `range`: The indices of the leaves, which can't be empty
+    pub fn range_proof(&self, range: Range<usize>) -> Result<RangeProof, MerkleTreeError> {
+        if range.is_empty() || range.end > self.len() {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "Invalid range {}..{} for a tree of {} leaves",
+                range.start,
+                range.end,
+                self.len()
+            )));
+        }
+
+        let leaves = (range.start..range.end)
+            .map(|i| Ok(MerkleTree::digest_to_hex(&self.digest(0, i)?)))
+            .collect::<Result<_, MerkleTreeError>>()?;
+        let mut siblings = vec![];
+        let (mut start, mut end) = (range.start, range.end);
+        for level in 0..self.height() - 1 {
+            if start % 2 == 1 {
+                siblings.push(MerkleTree::digest_to_hex(&self.digest(level, start - 1)?));
+            }
+            if end % 2 == 1 && end < self.level_len(level) {
+                siblings.push(MerkleTree::digest_to_hex(&self.digest(level, end)?));
+            }
+            start /= 2;
+            end = end.div_ceil(2);
+        }
+
+        Ok(RangeProof {
+            size: self.len(),
+            start: range.start,
+            leaves,
+            siblings,
+        })
+    }
+}
+
+impl RangeProof {
+    /// Returns the indices of the leaves of the proof, or `None` if they don't fit in a `usize`.
+    pub fn range(&self) -> Option<Range<usize>> {
+        let end = self.start.checked_add(self.leaves.len())?;
+        Some(self.start..end)
+    }
+
+    /// Verifies the proof against the root of a tree of the given size, returning the leaves it proves.
+    ///
+    /// The size is taken from the caller, as the one in the proof is not authenticated by it: with a bigger size, the copies of the last nodes hashed with themselves could be proved as leaves past the last one.
+    ///
+    /// # Parameters
+    /// - `size`: The trusted number of leaves of the tree
+    /// - `root`: The trusted root of the tree
+    pub fn verify(&self, size: usize, root: &MerkleHash) -> Result<&[MerkleHash], MerkleTreeError> {
+        let invalid =
+            |reason: &str| MerkleTreeError::InvalidHash(format!("Invalid range proof: {}", reason));
+        if self.size != size {
+            return Err(invalid("the proof is not for a tree of that size"));
+        }
+        let range = self
+            .range()
+            .filter(|range| !range.is_empty() && range.end <= self.size)
+            .ok_or_else(|| invalid("the range is not in the tree"))?;
+
+        let height = MerkleTree::height_of(self.size);
+        let mut siblings = self.siblings.iter();
+        let mut nodes = self.leaves.clone();
+        let (mut start, mut end) = (range.start, range.end);
+
+        for level in 0..height - 1 {
+            let level_len = self.size.div_ceil(1 << level);
+            let mut row = Vec::with_capacity(nodes.len() + 2);
+            if start % 2 == 1 {
+                row.push(
+                    siblings
+                        .next()
+                        .ok_or_else(|| invalid("missing siblings"))?
+                        .clone(),
+                );
+            }
+            row.append(&mut nodes);
+            if end % 2 == 1 {
+                let right = match end < level_len {
+                    true => siblings.next().ok_or_else(|| invalid("missing siblings"))?,
+                    false => &row[row.len() - 1],
+                };
+                row.push(right.clone());
+            }
+
+            nodes = row
+                .chunks(2)
+                .map(|pair| MerkleTree::combine_hashes(&pair[0], &pair[1]))
+                .collect();
+            start /= 2;
+            end = end.div_ceil(2);
+        }
+
+        if siblings.next().is_some() {
+            return Err(invalid("too many siblings"));
+        }
+        match nodes.first() == Some(root) {
+            true => Ok(&self.leaves),
+            false => Err(invalid("the leaves don't lead to the root")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::merkle_hash::MerkleHash;
+    use crate::merkle_tree::MerkleTree;
+
+    #[test]
+    fn ranges_are_proved_with_their_boundaries() {
+        let hashes: Vec<MerkleHash> = (0..37)
+            .map(|i| MerkleTree::get_hash_of(&format!("leaf{}", i)))
+            .collect();
+        let tree = MerkleTree::new_from_hashes(hashes.clone()).unwrap();
+        let root = tree.root().unwrap();
+
+        for start in 0..37 {
+            for end in start + 1..=37 {
+                let proof = tree.range_proof(start..end).unwrap();
+                assert!(proof.siblings.len() <= 2 * (tree.height() - 1));
+                assert_eq!(proof.verify(37, &root).unwrap(), &hashes[start..end]);
+            }
+        }
+
+        let mut proof = tree.range_proof(10..20).unwrap();
+        proof.leaves[4] = MerkleTree::get_hash_of(&"forged");
+        assert!(proof.verify(37, &root).is_err());
+        let mut proof = tree.range_proof(10..20).unwrap();
+        proof.start = 11;
+        assert!(proof.verify(37, &root).is_err());
+        proof.start = usize::MAX - 2;
+        assert_eq!(proof.range(), None);
+        assert!(proof.verify(37, &root).is_err());
+        assert!(tree.range_proof(5..5).is_err());
+
+        // The last leaf of a tree of 5 leaves is hashed with itself, so it can claim a copy of itself past the end.
+        let five = MerkleTree::new_from_hashes(hashes[..5].to_vec()).unwrap();
+        let mut proof = five.range_proof(4..5).unwrap();
+        proof.size = 6;
+        proof.leaves.push(hashes[4].clone());
+        assert!(proof.verify(6, &five.root().unwrap()).is_ok());
+        assert!(proof.verify(5, &five.root().unwrap()).is_err());
+        assert!(tree.range_proof(30..38).is_err());
+    }
+}
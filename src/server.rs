@@ -0,0 +1,316 @@
+use std::io::Read;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::merkle_hash::MerkleHash;
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+
+/// Largest request body accepted, in bytes.
+const MAX_BODY_LEN: u64 = 64 * 1024 * 1024;
+
+/// The leaves of a request that creates a tree or appends to it: hashes, or elements that are hashed first.
+#[derive(Deserialize)]
+struct Leaves {
+    #[serde(default)]
+    hashes: Vec<MerkleHash>,
+    #[serde(default)]
+    elements: Vec<String>,
+}
+
+/// The body of a verification request.
+#[derive(Deserialize)]
+struct Verification {
+    leaf: MerkleHash,
+    index: Option<u32>,
+}
+
+/// An error of a request, with the status code of its response.
+struct ApiError(u16, String);
+
+impl From<MerkleTreeError> for ApiError {
+    fn from(e: MerkleTreeError) -> Self {
+        ApiError(400, format!("{:?}", e))
+    }
+}
+
+/// A running `TreeServer`, which stops when `shutdown` is called.
+pub struct ServerHandle {
+    server: Arc<Server>,
+    address: SocketAddr,
+    tree: Arc<RwLock<MerkleTree>>,
+    stopping: Arc<AtomicBool>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+/// The `TreeServer` exposes a `MerkleTree` through a JSON REST API over HTTP.
+///
+/// Requests are answered by several worker threads that share the tree behind a read-write lock, so queries run concurrently and appends run one at a time. The routes are:
+/// - `POST /tree`, with `{"hashes": [...]}` or `{"elements": [...]}`: replaces the tree.
+/// - `POST /tree/leaves`, with the same body: appends leaves to the tree.
+/// - `GET /tree/root`: the root and the number of leaves.
+/// - `GET /tree/proof?index=<index>` or `GET /tree/proof?value=<hash>`: the proof of inclusion of a leaf.
+/// - `POST /tree/verify`, with `{"leaf": <hash>, "index": <index>}`: whether the leaf is in the tree, the index being optional.
+/// - `GET /tree/consistency?old_size=<size>`: a proof that the first `old_size` leaves are a prefix of the tree.
+///
+/// The API can replace the tree and has no authentication, so it only listens on loopback addresses.
+pub struct TreeServer {
+    server: Server,
+    address: SocketAddr,
+}
+
+impl TreeServer {
+    /// Listens on the given address, e.g. `127.0.0.1:0` to get an ephemeral port, without serving anything yet.
+    /// Addresses that aren't loopback addresses are rejected.
+    pub fn bind(address: &str) -> Result<TreeServer, MerkleTreeError> {
+        let addresses: Vec<SocketAddr> = address
+            .to_socket_addrs()
+            .map_err(|e| MerkleTreeError::InvalidValue(format!("{}: {}", address, e)))?
+            .collect();
+        if addresses.is_empty() || addresses.iter().any(|a| !a.ip().is_loopback()) {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "{} is not a loopback address, the API is only served to this machine",
+                address
+            )));
+        }
+
+        let server = Server::http(&addresses[..])
+            .map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))?;
+        let address = server.server_addr().to_ip().ok_or_else(|| {
+            MerkleTreeError::FailedToProcessFile("The server has no IP address".to_string())
+        })?;
+        Ok(TreeServer { server, address })
+    }
+
+    /// Returns the address the server listens on.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Starts serving a tree with the given number of worker threads.
+    pub fn serve(self, tree: MerkleTree, workers: usize) -> ServerHandle {
+        let server = Arc::new(self.server);
+        let tree = Arc::new(RwLock::new(tree));
+        let stopping = Arc::new(AtomicBool::new(false));
+        let workers = (0..workers.max(1))
+            .map(|_| {
+                let server = Arc::clone(&server);
+                let tree = Arc::clone(&tree);
+                let stopping = Arc::clone(&stopping);
+                thread::spawn(move || {
+                    while !stopping.load(Ordering::SeqCst) {
+                        match server.recv() {
+                            Ok(request) => TreeServer::respond(request, &tree),
+                            Err(_) => break,
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        ServerHandle {
+            server,
+            address: self.address,
+            tree,
+            stopping,
+            workers,
+        }
+    }
+
+    /// Starts serving a tree on the given loopback address with the given number of worker threads.
+    pub fn start(
+        address: &str,
+        tree: MerkleTree,
+        workers: usize,
+    ) -> Result<ServerHandle, MerkleTreeError> {
+        Ok(TreeServer::bind(address)?.serve(tree, workers))
+    }
+
+    fn respond(mut request: Request, tree: &RwLock<MerkleTree>) {
+        let mut body = String::new();
+        let read = request
+            .as_reader()
+            .take(MAX_BODY_LEN)
+            .read_to_string(&mut body);
+
+        let result = match read {
+            Ok(_) => TreeServer::route(request.method(), request.url(), &body, tree),
+            Err(e) => Err(ApiError(400, e.to_string())),
+        };
+        let (status, body) = match result {
+            Ok(body) => (200, body),
+            Err(ApiError(status, message)) => (status, json!({ "error": message })),
+        };
+
+        let response = Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
+        let _ = request.respond(response);
+    }
+
+    /// Answers a request, returning the JSON body of the response.
+    fn route(
+        method: &Method,
+        url: &str,
+        body: &str,
+        tree: &RwLock<MerkleTree>,
+    ) -> Result<Value, ApiError> {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let params = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| Ok((TreeServer::decode(key)?, TreeServer::decode(value)?)))
+            .collect::<Result<Vec<_>, ApiError>>()?;
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+        };
+
+        match (method, path) {
+            (Method::Post, "/tree") => {
+                let leaves = TreeServer::leaves(body)?;
+                let new_tree = MerkleTree::new_from_hashes(leaves)?;
+                let mut tree = TreeServer::write(tree)?;
+                *tree = new_tree;
+                TreeServer::summary(&tree)
+            }
+            (Method::Post, "/tree/leaves") => {
+                let leaves = TreeServer::leaves(body)?;
+                let mut tree = TreeServer::write(tree)?;
+                tree.append_hashes(leaves)?;
+                TreeServer::summary(&tree)
+            }
+            (Method::Get, "/tree/root") => TreeServer::summary(&*TreeServer::read(tree)?),
+            (Method::Get, "/tree/proof") => {
+                let tree = TreeServer::read(tree)?;
+                let index = match (param("index"), param("value")) {
+                    (Some(index), _) => index
+                        .parse::<usize>()
+                        .map_err(|e| ApiError(400, e.to_string()))?,
+                    (None, Some(value)) => tree
+                        .position_of(&value)
+                        .ok_or_else(|| ApiError(404, "The value is not in the tree".to_string()))?,
+                    (None, None) => {
+                        return Err(ApiError(400, "Missing index or value".to_string()))
+                    }
+                };
+                let leaf = tree
+                    .node(0, index)
+                    .ok_or_else(|| ApiError(404, format!("There is no leaf at index {}", index)))?;
+                let proof = tree.proof_of_inclusion_with_index(&leaf, index as u32)?;
+                Ok(json!({ "index": index, "root": tree.root()?, "proof": proof }))
+            }
+            (Method::Post, "/tree/verify") => {
+                let verification: Verification =
+                    serde_json::from_str(body).map_err(|e| ApiError(400, e.to_string()))?;
+                let tree = TreeServer::read(tree)?;
+                let included = match verification.index {
+                    Some(index) => tree.verify_with_index(&verification.leaf, index),
+                    None => tree.verify(&verification.leaf),
+                };
+                Ok(json!({ "included": included }))
+            }
+            (Method::Get, "/tree/consistency") => {
+                let old_size = param("old_size")
+                    .ok_or_else(|| ApiError(400, "Missing old_size".to_string()))?
+                    .parse::<usize>()
+                    .map_err(|e| ApiError(400, e.to_string()))?;
+                let proof = TreeServer::read(tree)?.consistency_proof(old_size)?;
+                Ok(json!(proof))
+            }
+            _ => Err(ApiError(404, format!("No route for {} {}", method, path))),
+        }
+    }
+
+    /// Decodes a percent-encoded key or value of the query, where `+` stands for a space.
+    fn decode(component: &str) -> Result<String, ApiError> {
+        let invalid = || ApiError(400, format!("Invalid query component {:?}", component));
+        let bytes = component.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'%' => {
+                    let hex = bytes.get(i + 1..i + 3).ok_or_else(invalid)?;
+                    if !hex.iter().all(u8::is_ascii_hexdigit) {
+                        return Err(invalid());
+                    }
+                    let hex = std::str::from_utf8(hex).map_err(|_| invalid())?;
+                    decoded.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+                    i += 3;
+                }
+                b'+' => {
+                    decoded.push(b' ');
+                    i += 1;
+                }
+                byte => {
+                    decoded.push(byte);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8(decoded).map_err(|_| invalid())
+    }
+
+    fn leaves(body: &str) -> Result<Vec<MerkleHash>, ApiError> {
+        let leaves: Leaves =
+            serde_json::from_str(body).map_err(|e| ApiError(400, e.to_string()))?;
+        let mut hashes = leaves.hashes;
+        hashes.extend(leaves.elements.iter().map(MerkleTree::get_hash_of));
+        Ok(hashes)
+    }
+
+    fn summary(tree: &MerkleTree) -> Result<Value, ApiError> {
+        Ok(json!({ "size": tree.len(), "root": tree.root().ok() }))
+    }
+
+    fn read(
+        tree: &RwLock<MerkleTree>,
+    ) -> Result<std::sync::RwLockReadGuard<'_, MerkleTree>, ApiError> {
+        tree.read()
+            .map_err(|_| ApiError(500, "The tree is unavailable".to_string()))
+    }
+
+    fn write(
+        tree: &RwLock<MerkleTree>,
+    ) -> Result<std::sync::RwLockWriteGuard<'_, MerkleTree>, ApiError> {
+        tree.write()
+            .map_err(|_| ApiError(500, "The tree is unavailable".to_string()))
+    }
+}
+
+impl ServerHandle {
+    /// Returns the address the server listens on.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Returns the tree being served.
+    pub fn tree(&self) -> Arc<RwLock<MerkleTree>> {
+        Arc::clone(&self.tree)
+    }
+
+    /// Blocks until the server stops.
+    pub fn wait(self) {
+        for worker in self.workers {
+            let _ = worker.join();
+        }
+    }
+
+    /// Stops the server, waiting for the requests being answered.
+    pub fn shutdown(self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        for _ in self.workers.iter() {
+            self.server.unblock();
+        }
+        self.wait();
+    }
+}
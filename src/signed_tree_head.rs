@@ -0,0 +1,271 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+
+use crate::merkle_hash::MerkleHash;
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+use crate::node_store::NodeStore;
+use crate::proof_of_inclusion::ProofOfInclusion;
+use crate::tree_mode::HashAlgorithm;
+
+/// Prefix of the signed bytes of a tree head, so its signatures can't be mistaken for signatures of anything else.
+const SIGNED_PREFIX: &str = "rusty-merkle-tree tree head v1";
+
+/// The `TreeHead` struct describes a tree at some point of time: its size, its root and the hash algorithm of its nodes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TreeHead {
+    pub tree_size: u64,
+    pub root: MerkleHash,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub hash_algorithm: HashAlgorithm,
+}
+
+/// The `SignedTreeHead` struct is a `TreeHead` signed with the Ed25519 key of a log, so clients that trust the key can check proofs against its root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedTreeHead {
+    #[serde(flatten)]
+    pub head: TreeHead,
+    /// The signature of the head, in hex.
+    pub signature: String,
+}
+
+impl TreeHead {
+    /// Creates the head of a tree, timestamped now.
+    pub fn new<S: NodeStore>(tree: &MerkleTree<S>) -> Result<TreeHead, MerkleTreeError> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| MerkleTreeError::InvalidValue(e.to_string()))?
+            .as_millis() as u64;
+
+        Ok(TreeHead {
+            tree_size: tree.len() as u64,
+            root: tree.root()?,
+            timestamp,
+            hash_algorithm: HashAlgorithm::Sha3_256,
+        })
+    }
+
+    /// Returns the bytes that are signed: every field on its own line, after a prefix.
+    pub fn signed_bytes(&self) -> Vec<u8> {
+        format!(
+            "{}\n{}\n{}\n{}\n{}\n",
+            SIGNED_PREFIX,
+            self.hash_algorithm.name(),
+            self.tree_size,
+            self.root,
+            self.timestamp
+        )
+        .into_bytes()
+    }
+
+    /// Signs the head with the key of the log.
+    pub fn sign(self, key: &SigningKey) -> SignedTreeHead {
+        let signature = key.sign(&self.signed_bytes());
+        SignedTreeHead {
+            head: self,
+            signature: MerkleTree::bytes_to_hex(&signature.to_bytes()),
+        }
+    }
+}
+
+impl SignedTreeHead {
+    /// Checks the signature of the head with the key of the log.
+    pub fn verify(&self, key: &VerifyingKey) -> Result<(), MerkleTreeError> {
+        let bytes: [u8; 64] = hex_to_bytes(&self.signature)?.try_into().map_err(|_| {
+            MerkleTreeError::InvalidSignature("A signature has 64 bytes".to_string())
+        })?;
+
+        key.verify(&self.head.signed_bytes(), &Signature::from_bytes(&bytes))
+            .map_err(|_| {
+                MerkleTreeError::InvalidSignature(
+                    "The tree head was not signed with the key".to_string(),
+                )
+            })
+    }
+
+    /// Checks the signature of the head, and that the proof leads to its root from a leaf of the tree.
+    /// Proofs of leaves past the last one are rejected by their index, see [`ProofOfInclusion::index`].
+    pub fn verify_proof(
+        &self,
+        key: &VerifyingKey,
+        proof: &ProofOfInclusion,
+    ) -> Result<bool, MerkleTreeError> {
+        self.verify(key)?;
+        Ok((proof.index() as u64) < self.head.tree_size && proof.verify(&self.head.root))
+    }
+
+    /// Returns the signed tree head as JSON.
+    pub fn to_json(&self) -> Result<String, MerkleTreeError> {
+        serde_json::to_string_pretty(self).map_err(|e| MerkleTreeError::InvalidValue(e.to_string()))
+    }
+
+    /// Parses a signed tree head from JSON, without checking its signature.
+    pub fn from_json(json: &str) -> Result<SignedTreeHead, MerkleTreeError> {
+        serde_json::from_str(json).map_err(|e| MerkleTreeError::InvalidValue(e.to_string()))
+    }
+
+    /// Reads a signed tree head from a JSON file.
+    pub fn read(path: impl AsRef<Path>) -> Result<SignedTreeHead, MerkleTreeError> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))?;
+        SignedTreeHead::from_json(&json)
+    }
+
+    /// Writes the signed tree head to a JSON file.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), MerkleTreeError> {
+        std::fs::write(path, self.to_json()?)
+            .map_err(|e| MerkleTreeError::FailedToWriteFile(e.to_string()))
+    }
+}
+
+/// Generates a new random signing key.
+pub fn generate_key() -> SigningKey {
+    SigningKey::generate(&mut OsRng)
+}
+
+/// Writes the secret key to the given path, and its public key next to it with a `.pub` extension, both in hex.
+/// The secret key file must not exist, and is only readable by its owner.
+pub fn write_keys(path: impl AsRef<Path>, key: &SigningKey) -> Result<(), MerkleTreeError> {
+    let path = path.as_ref();
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(path)
+        .and_then(|mut file| {
+            file.write_all((MerkleTree::bytes_to_hex(key.as_bytes()) + "\n").as_bytes())?;
+            file.sync_all()
+        })
+        .map_err(|e| MerkleTreeError::FailedToWriteFile(format!("{}: {}", path.display(), e)))?;
+
+    let public = MerkleTree::bytes_to_hex(key.verifying_key().as_bytes()) + "\n";
+    std::fs::write(path.with_extension("pub"), public)
+        .map_err(|e| MerkleTreeError::FailedToWriteFile(e.to_string()))
+}
+
+/// Reads a secret key written by `write_keys`.
+pub fn read_signing_key(path: impl AsRef<Path>) -> Result<SigningKey, MerkleTreeError> {
+    Ok(SigningKey::from_bytes(&read_key_bytes(path)?))
+}
+
+/// Reads a public key written by `write_keys`.
+pub fn read_verifying_key(path: impl AsRef<Path>) -> Result<VerifyingKey, MerkleTreeError> {
+    VerifyingKey::from_bytes(&read_key_bytes(path)?)
+        .map_err(|_| MerkleTreeError::InvalidValue("Invalid public key".to_string()))
+}
+
+/// Parses a public key from its hex representation.
+pub fn verifying_key_from_hex(hex: &str) -> Result<VerifyingKey, MerkleTreeError> {
+    let bytes = MerkleTree::hex_to_digest(hex.trim())
+        .map_err(|_| MerkleTreeError::InvalidValue(format!("Invalid public key: {:?}", hex)))?;
+    VerifyingKey::from_bytes(&bytes)
+        .map_err(|_| MerkleTreeError::InvalidValue(format!("Invalid public key: {:?}", hex)))
+}
+
+fn read_key_bytes(path: impl AsRef<Path>) -> Result<[u8; 32], MerkleTreeError> {
+    let hex = std::fs::read_to_string(path)
+        .map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))?;
+    MerkleTree::hex_to_digest(hex.trim())
+        .map_err(|_| MerkleTreeError::InvalidValue("A key file holds 32 bytes in hex".to_string()))
+}
+
+/// Converts a hexadecimal string of any even length to its bytes.
+pub(crate) fn hex_to_bytes(hex: &str) -> Result<Vec<u8>, MerkleTreeError> {
+    let invalid = || MerkleTreeError::InvalidValue(format!("Invalid hex: {:?}", hex));
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::direction::Direction;
+    use crate::merkle_tree::MerkleTree;
+    use crate::proof_of_inclusion::ProofOfInclusion;
+    use crate::signed_tree_head::{
+        generate_key, read_signing_key, read_verifying_key, write_keys, SignedTreeHead, TreeHead,
+    };
+
+    #[test]
+    fn signed_heads_authenticate_roots() {
+        let tree = MerkleTree::new_from_hashables(vec!["a", "b", "c"]).unwrap();
+        let key = generate_key();
+        let sth = TreeHead::new(&tree).unwrap().sign(&key);
+        assert_eq!(sth.head.tree_size, 3);
+        assert!(sth.verify(&key.verifying_key()).is_ok());
+
+        let proof = tree
+            .proof_of_inclusion(&MerkleTree::get_hash_of(&"b"))
+            .unwrap();
+        assert!(sth.verify_proof(&key.verifying_key(), &proof).unwrap());
+
+        let parsed = SignedTreeHead::from_json(&sth.to_json().unwrap()).unwrap();
+        assert_eq!(parsed, sth);
+
+        let mut tampered = sth.clone();
+        tampered.head.tree_size = 4;
+        assert!(tampered.verify(&key.verifying_key()).is_err());
+        assert!(sth.verify(&generate_key().verifying_key()).is_err());
+    }
+
+    #[test]
+    fn proofs_past_the_last_leaf_are_rejected() {
+        let tree = MerkleTree::new_from_hashables(vec!["a", "b", "c", "d", "e"]).unwrap();
+        let key = generate_key();
+        let sth = TreeHead::new(&tree).unwrap().sign(&key);
+
+        // A proof of a leaf past the last one, see `ProofOfInclusion::index`.
+        let leaf = MerkleTree::get_hash_of(&"e");
+        let proof = tree.proof_of_inclusion_with_index(&leaf, 4).unwrap();
+        let mut path: Vec<(String, Direction)> = proof.iter().cloned().collect();
+        path[1] = (tree.node(1, 2).unwrap(), Direction::Left);
+        let forged = ProofOfInclusion::new_from(leaf, path);
+
+        assert_eq!(forged.index(), 6);
+        assert!(forged.verify(&tree.root().unwrap()));
+        assert!(sth.verify_proof(&key.verifying_key(), &proof).unwrap());
+        assert!(!sth.verify_proof(&key.verifying_key(), &forged).unwrap());
+    }
+
+    #[test]
+    fn keys_are_written_and_read() {
+        let dir = std::env::temp_dir().join(format!("merkle-sth-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("log.key");
+
+        let key = generate_key();
+        write_keys(&path, &key).unwrap();
+        assert_eq!(read_signing_key(&path).unwrap(), key);
+        assert_eq!(
+            read_verifying_key(path.with_extension("pub")).unwrap(),
+            key.verifying_key()
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        assert!(write_keys(&path, &generate_key()).is_err());
+        assert_eq!(read_signing_key(&path).unwrap(), key);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,427 @@
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+
+use crate::abi_type::AbiType;
+use crate::merkle_hash::MerkleHash;
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+
+/// Format tag used by OpenZeppelin's `StandardMerkleTree` dumps.
+const DUMP_FORMAT: &str = "standard-v1";
+
+/// A Merkle Tree compatible with OpenZeppelin's `StandardMerkleTree` and `MerkleProof.verify`.
+///
+/// Leaves are the Keccak-256 of the Keccak-256 of the ABI encoded values, pairs are sorted before being hashed, so proofs don't need directions, and the nodes are laid out as OpenZeppelin does, so the root, the dump and the proofs are identical to the ones generated by its JavaScript library.
+///
+/// Hashes are represented as `0x` prefixed hex strings, as expected by Solidity tooling.
+///
+/// # Methods
+/// - `of`: Creates a new tree from a list of values and their types.
+/// - `load`: Creates a tree from a dump generated by `dump`, or by OpenZeppelin's library.
+/// - `root`: Returns the root of the tree.
+/// - `proof`: Returns the `bytes32[]` proof for the value at a given index.
+/// - `verify`: Verifies a proof, as `MerkleProof.verify` does.
+#[derive(Debug)]
+pub struct SortedPairTree {
+    tree: Vec<[u8; 32]>,
+    values: Vec<(Vec<String>, usize)>,
+    leaf_encoding: Vec<AbiType>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TreeDump {
+    format: String,
+    leaf_encoding: Vec<String>,
+    tree: Vec<String>,
+    values: Vec<ValueDump>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ValueDump {
+    value: Vec<serde_json::Value>,
+    tree_index: usize,
+}
+
+#[derive(Serialize)]
+struct ProofsExport {
+    root: MerkleHash,
+    #[serde(rename = "leafEncoding")]
+    leaf_encoding: Vec<String>,
+    proofs: Vec<ProofExport>,
+}
+
+#[derive(Serialize)]
+struct ProofExport {
+    value: Vec<String>,
+    leaf: MerkleHash,
+    proof: Vec<MerkleHash>,
+}
+
+impl SortedPairTree {
+    /// Creates a new tree from a list of values, each one being a list of fields of the given types.
+    ///
+    /// # Parameters
+    /// - `values`: The values to include in the tree
+    /// - `leaf_encoding`: The Solidity types of the fields of every value
+    pub fn of(
+        values: Vec<Vec<String>>,
+        leaf_encoding: Vec<AbiType>,
+    ) -> Result<SortedPairTree, MerkleTreeError> {
+        if values.is_empty() {
+            return Err(MerkleTreeError::FailedToBuild(
+                "Expected at least one value".to_string(),
+            ));
+        }
+
+        let mut hashed = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| Ok((SortedPairTree::hash_leaf(&leaf_encoding, value)?, i)))
+            .collect::<Result<Vec<_>, MerkleTreeError>>()?;
+        hashed.sort();
+
+        let len = 2 * hashed.len() - 1;
+        let mut tree = vec![[0u8; 32]; len];
+        let mut tree_indexes = vec![0; values.len()];
+
+        for (i, (leaf, value_index)) in hashed.iter().enumerate() {
+            tree[len - 1 - i] = *leaf;
+            tree_indexes[*value_index] = len - 1 - i;
+        }
+
+        for i in (0..len - hashed.len()).rev() {
+            tree[i] = SortedPairTree::hash_pair(&tree[2 * i + 1], &tree[2 * i + 2]);
+        }
+
+        let values = values.into_iter().zip(tree_indexes).collect();
+
+        Ok(SortedPairTree {
+            tree,
+            values,
+            leaf_encoding,
+        })
+    }
+
+    /// Creates a tree from a JSON dump in OpenZeppelin's `standard-v1` format, checking that it is consistent.
+    pub fn load(dump: &str) -> Result<SortedPairTree, MerkleTreeError> {
+        let dump: TreeDump = serde_json::from_str(dump)
+            .map_err(|e| MerkleTreeError::FailedToProcessFile(format!("Invalid dump: {}", e)))?;
+
+        if dump.format != DUMP_FORMAT {
+            return Err(MerkleTreeError::FailedToProcessFile(format!(
+                "Unknown dump format: {}",
+                dump.format
+            )));
+        }
+
+        let leaf_encoding = dump
+            .leaf_encoding
+            .iter()
+            .map(|name| AbiType::parse(name))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let tree = dump
+            .tree
+            .iter()
+            .map(|node| AbiType::Bytes32.encode(node))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let values = dump
+            .values
+            .into_iter()
+            .map(|v| {
+                let fields = v.value.iter().map(SortedPairTree::json_to_string).collect();
+                (fields, v.tree_index)
+            })
+            .collect();
+
+        let tree = SortedPairTree {
+            tree,
+            values,
+            leaf_encoding,
+        };
+        tree.validate()?;
+        Ok(tree)
+    }
+
+    /// Checks that every node is the hash of its children and that every value is stored in its leaf.
+    fn validate(&self) -> Result<(), MerkleTreeError> {
+        let len = self.tree.len();
+        let invalid = |msg: &str| Err(MerkleTreeError::FailedToBuild(msg.to_string()));
+
+        if len.is_multiple_of(2) {
+            return invalid("Invalid tree length");
+        }
+
+        for i in 0..len / 2 {
+            if self.tree[i]
+                != SortedPairTree::hash_pair(&self.tree[2 * i + 1], &self.tree[2 * i + 2])
+            {
+                return invalid("Tree node does not match the hash of its children");
+            }
+        }
+
+        for (value, tree_index) in self.values.iter() {
+            if *tree_index < len / 2 || *tree_index >= len {
+                return invalid("Value points to a node that is not a leaf");
+            }
+            if self.tree[*tree_index] != SortedPairTree::hash_leaf(&self.leaf_encoding, value)? {
+                return invalid("Value does not match its leaf");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the hash of a leaf: `keccak256(bytes.concat(keccak256(abi.encode(value))))`.
+    ///
+    /// # Parameters
+    /// - `leaf_encoding`: The Solidity types of the fields of the value
+    /// - `value`: The fields of the value
+    pub fn hash_leaf(
+        leaf_encoding: &[AbiType],
+        value: &[String],
+    ) -> Result<[u8; 32], MerkleTreeError> {
+        let encoded = AbiType::encode_all(leaf_encoding, value)?;
+        let inner = Keccak256::digest(&encoded);
+        Ok(Keccak256::digest(&inner).into())
+    }
+
+    /// Hashes two nodes in ascending order, as `MerkleProof` does.
+    fn hash_pair(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+        let (first, second) = if a <= b { (a, b) } else { (b, a) };
+
+        let mut hasher = Keccak256::new();
+        hasher.update(first);
+        hasher.update(second);
+        hasher.finalize().into()
+    }
+
+    /// Returns the root of the tree.
+    pub fn root(&self) -> MerkleHash {
+        SortedPairTree::to_hex(&self.tree[0])
+    }
+
+    /// Returns the number of values in the tree.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns true if the tree has no values. Trees are never empty, it is provided for completeness.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the fields of the value at the given index.
+    pub fn value(&self, index: usize) -> Option<&Vec<String>> {
+        self.values.get(index).map(|(value, _)| value)
+    }
+
+    /// Returns the types used to encode the leaves.
+    pub fn leaf_encoding(&self) -> &[AbiType] {
+        &self.leaf_encoding
+    }
+
+    /// Returns the hash of the leaf of the value at the given index.
+    pub fn leaf(&self, index: usize) -> Result<MerkleHash, MerkleTreeError> {
+        let (_, tree_index) = self.value_entry(index)?;
+        Ok(SortedPairTree::to_hex(&self.tree[tree_index]))
+    }
+
+    /// Returns the proof for the value at the given index, that can be passed as the `bytes32[]` argument of `MerkleProof.verify`.
+    pub fn proof(&self, index: usize) -> Result<Vec<MerkleHash>, MerkleTreeError> {
+        let (_, mut tree_index) = self.value_entry(index)?;
+
+        let mut proof = vec![];
+        while tree_index > 0 {
+            let sibling = if !tree_index.is_multiple_of(2) {
+                tree_index + 1
+            } else {
+                tree_index - 1
+            };
+            proof.push(SortedPairTree::to_hex(&self.tree[sibling]));
+            tree_index = (tree_index - 1) / 2;
+        }
+
+        Ok(proof)
+    }
+
+    /// Verifies that a leaf is included in the tree with the given root, as `MerkleProof.verify` does.
+    ///
+    /// # Parameters
+    /// - `root`: The `0x` prefixed root of the tree
+    /// - `leaf`: The `0x` prefixed hash of the leaf
+    /// - `proof`: The `0x` prefixed hashes of the proof
+    pub fn verify(root: &MerkleHash, leaf: &MerkleHash, proof: &[MerkleHash]) -> bool {
+        let mut computed = match AbiType::Bytes32.encode(leaf) {
+            Ok(leaf) => leaf,
+            Err(_) => return false,
+        };
+
+        for hash in proof {
+            match AbiType::Bytes32.encode(hash) {
+                Ok(hash) => computed = SortedPairTree::hash_pair(&computed, &hash),
+                Err(_) => return false,
+            }
+        }
+
+        AbiType::Bytes32
+            .encode(root)
+            .is_ok_and(|root| root == computed)
+    }
+
+    /// Returns the tree as JSON, in the `standard-v1` format used by OpenZeppelin's `StandardMerkleTree.dump`.
+    pub fn dump(&self) -> String {
+        let dump = TreeDump {
+            format: DUMP_FORMAT.to_string(),
+            leaf_encoding: self.encoding_names(),
+            tree: self.tree.iter().map(SortedPairTree::to_hex).collect(),
+            values: self
+                .values
+                .iter()
+                .map(|(value, tree_index)| ValueDump {
+                    value: value
+                        .iter()
+                        .map(|field| serde_json::Value::String(field.clone()))
+                        .collect(),
+                    tree_index: *tree_index,
+                })
+                .collect(),
+        };
+
+        serde_json::to_string_pretty(&dump).unwrap_or_default()
+    }
+
+    /// Returns the root and the proof of every value as JSON, ready to be consumed by front-ends.
+    pub fn proofs_json(&self) -> Result<String, MerkleTreeError> {
+        let proofs = (0..self.len())
+            .map(|i| {
+                Ok(ProofExport {
+                    value: self.values[i].0.clone(),
+                    leaf: self.leaf(i)?,
+                    proof: self.proof(i)?,
+                })
+            })
+            .collect::<Result<Vec<_>, MerkleTreeError>>()?;
+
+        let export = ProofsExport {
+            root: self.root(),
+            leaf_encoding: self.encoding_names(),
+            proofs,
+        };
+
+        serde_json::to_string_pretty(&export)
+            .map_err(|e| MerkleTreeError::FailedToBuild(format!("Failed to export proofs: {}", e)))
+    }
+
+    /// Prints the values of the tree and their leaves.
+    pub fn print(&self) {
+        println!("ROOT: {:?}", self.root());
+        for (i, (value, tree_index)) in self.values.iter().enumerate() {
+            println!(
+                "- {}: {:?} - {:?}",
+                i,
+                value,
+                SortedPairTree::to_hex(&self.tree[*tree_index])
+            );
+        }
+    }
+
+    fn value_entry(&self, index: usize) -> Result<(&Vec<String>, usize), MerkleTreeError> {
+        match self.values.get(index) {
+            Some((value, tree_index)) => Ok((value, *tree_index)),
+            None => Err(MerkleTreeError::InvalidHash(format!(
+                "There is no value at index {}",
+                index
+            ))),
+        }
+    }
+
+    fn encoding_names(&self) -> Vec<String> {
+        self.leaf_encoding
+            .iter()
+            .map(|t| t.name().to_string())
+            .collect()
+    }
+
+    fn json_to_string(value: &serde_json::Value) -> String {
+        match value {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }
+    }
+
+    fn to_hex(bytes: &[u8; 32]) -> MerkleHash {
+        format!("0x{}", MerkleTree::bytes_to_hex(bytes))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::abi_type::AbiType;
+    use crate::sorted_pair_tree::SortedPairTree;
+
+    fn values() -> Vec<Vec<String>> {
+        vec![
+            vec![
+                "0x1111111111111111111111111111111111111111".to_string(),
+                "5000000000000000000".to_string(),
+            ],
+            vec![
+                "0x2222222222222222222222222222222222222222".to_string(),
+                "2500000000000000000".to_string(),
+            ],
+        ]
+    }
+
+    #[test]
+    fn root_matches_openzeppelin() {
+        let tree = SortedPairTree::of(values(), vec![AbiType::Address, AbiType::Uint256]).unwrap();
+
+        assert_eq!(
+            tree.root(),
+            "0xd4dee0beab2d53f2cc83e567171bd2820e49898130a22622b10ead383e90bd77"
+        );
+    }
+
+    #[test]
+    fn proofs_verify_for_every_value() {
+        let values: Vec<Vec<String>> = (0..7)
+            .map(|i| vec![format!("0x{:040x}", i + 1), format!("{}", 1000 * i)])
+            .collect();
+        let tree = SortedPairTree::of(values, vec![AbiType::Address, AbiType::Uint256]).unwrap();
+
+        for i in 0..tree.len() {
+            let proof = tree.proof(i).unwrap();
+            assert!(SortedPairTree::verify(
+                &tree.root(),
+                &tree.leaf(i).unwrap(),
+                &proof
+            ));
+        }
+
+        let wrong = tree.proof(0).unwrap();
+        assert!(!SortedPairTree::verify(
+            &tree.root(),
+            &tree.leaf(1).unwrap(),
+            &wrong
+        ));
+    }
+
+    #[test]
+    fn dump_and_load() {
+        let tree = SortedPairTree::of(values(), vec![AbiType::Address, AbiType::Uint256]).unwrap();
+
+        let loaded = SortedPairTree::load(&tree.dump()).unwrap();
+
+        assert_eq!(loaded.root(), tree.root());
+        assert_eq!(loaded.proof(1).unwrap(), tree.proof(1).unwrap());
+
+        let tampered = tree
+            .dump()
+            .replace("5000000000000000000", "6000000000000000000");
+        assert!(SortedPairTree::load(&tampered).is_err());
+    }
+}
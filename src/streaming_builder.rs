@@ -0,0 +1,167 @@
+use std::io::{BufRead, BufReader, Read};
+
+use crate::merkle_hash::MerkleHash;
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+
+/// Computes the root of a Merkle Tree from a stream of hashes, keeping only the right edge of the tree.
+///
+/// For every level it stores at most one hash, the root of the last complete subtree waiting for its right sibling, so the memory used is O(log n), with n = number of leaf hashes. The root is the same one a `MerkleTree` built from the same hashes has.
+///
+/// # Methods
+/// - `new`: Creates an empty builder.
+/// - `from_hashes`: Creates a builder from an iterator of hashes.
+/// - `from_reader`: Creates a builder from a reader with one hash per line.
+/// - `push_hash`: Adds a hash as the next leaf.
+/// - `root`: Returns the root of the leaves added so far.
+#[derive(Debug, Default)]
+pub struct StreamingBuilder {
+    frontier: Vec<Option<MerkleHash>>,
+    len: usize,
+}
+
+impl StreamingBuilder {
+    /// Creates an empty builder.
+    pub fn new() -> Self {
+        StreamingBuilder::default()
+    }
+
+    /// Creates a builder with all the hashes of an iterator as leaves.
+    pub fn from_hashes(
+        hashes: impl IntoIterator<Item = MerkleHash>,
+    ) -> Result<Self, MerkleTreeError> {
+        let mut builder = StreamingBuilder::new();
+        for hash in hashes {
+            builder.push_hash(hash)?;
+        }
+        Ok(builder)
+    }
+
+    /// Creates a builder reading one hash per line, skipping empty lines, as the elements files used by the CLI.
+    pub fn from_reader(reader: impl Read) -> Result<Self, MerkleTreeError> {
+        let mut builder = StreamingBuilder::new();
+
+        for line in BufReader::new(reader).lines() {
+            let line = line.map_err(|e| {
+                MerkleTreeError::FailedToProcessFile(format!("Failed to read line: {}", e))
+            })?;
+
+            let trimmed = line.trim();
+            if !trimmed.is_empty() {
+                builder.push_hash(trimmed.to_string())?;
+            }
+        }
+
+        Ok(builder)
+    }
+
+    /// Adds a hash as the next leaf.
+    /// The hash is read as `MerkleTree` reads its leaves, so hashes that aren't 64 lowercase hexadecimal characters are rejected.
+    pub fn push_hash(&mut self, hash: MerkleHash) -> Result<(), MerkleTreeError> {
+        MerkleTree::hex_to_digest(&hash)?;
+        self.push_node(hash);
+        Ok(())
+    }
+
+    /// Adds an element that will be hashed before adding it as the next leaf.
+    pub fn push_data(&mut self, data: impl AsRef<[u8]>) {
+        self.push_node(MerkleTree::get_hash_of(&data));
+    }
+
+    /// Adds a valid hash as the next leaf, merging it with the complete subtrees to its left.
+    fn push_node(&mut self, hash: MerkleHash) {
+        let mut node = hash;
+        let mut level = 0;
+
+        while let Some(Some(left)) = self.frontier.get_mut(level).map(Option::take) {
+            node = MerkleTree::combine_hashes(&left, &node);
+            level += 1;
+        }
+
+        if level == self.frontier.len() {
+            self.frontier.push(Some(node));
+        } else {
+            self.frontier[level] = Some(node);
+        }
+
+        self.len += 1;
+    }
+
+    /// Returns the number of leaves added.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if no leaves were added.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the root of the leaves added so far, or `None` if there are none.
+    ///
+    /// The right edge is folded as `MerkleTree` does: when a level has an odd number of nodes, the last one is hashed with itself.
+    pub fn root(&self) -> Option<MerkleHash> {
+        let mut carry: Option<MerkleHash> = None;
+
+        for (level, peak) in self.frontier.iter().enumerate() {
+            let nodes_in_level = (self.len >> level) + carry.is_some() as usize;
+            if nodes_in_level == 1 {
+                return carry.or_else(|| peak.clone());
+            }
+
+            carry = match (peak, carry) {
+                (Some(peak), Some(carry)) => Some(MerkleTree::combine_hashes(peak, &carry)),
+                (Some(peak), None) => Some(MerkleTree::combine_hashes(peak, peak)),
+                (None, Some(carry)) => Some(MerkleTree::combine_hashes(&carry, &carry)),
+                (None, None) => None,
+            };
+        }
+
+        carry
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::merkle_tree::MerkleTree;
+    use crate::streaming_builder::StreamingBuilder;
+
+    #[test]
+    fn root_matches_tree_at_every_size() {
+        let data: Vec<String> = (0..40).map(|i| format!("something{}", i)).collect();
+        let mut builder = StreamingBuilder::new();
+
+        assert_eq!(builder.root(), None);
+
+        for size in 1..=data.len() {
+            builder.push_data(&data[size - 1]);
+
+            let tree = MerkleTree::new_from_hashables(data[..size].to_vec()).unwrap();
+            assert_eq!(
+                builder.root().as_ref(),
+                tree.root().ok().as_ref(),
+                "size {}",
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn root_from_reader() {
+        let hashes: Vec<String> = (0..11)
+            .map(|i| MerkleTree::get_hash_of(&format!("something{}", i)))
+            .collect();
+        let file = format!("{}\n\n", hashes.join("\n"));
+
+        let builder = StreamingBuilder::from_reader(file.as_bytes()).unwrap();
+        let tree = MerkleTree::new_from_hashes(hashes.clone()).unwrap();
+
+        assert_eq!(builder.len(), 11);
+        assert_eq!(builder.root().as_ref(), tree.root().ok().as_ref());
+
+        let uppercase: Vec<String> = hashes.iter().map(|h| h.to_uppercase()).collect();
+        assert!(StreamingBuilder::from_hashes(uppercase.clone()).is_err());
+        assert!(MerkleTree::new_from_hashes(uppercase).is_err());
+        assert!(StreamingBuilder::from_reader("something00\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::memory_store::MemoryStore;
+use crate::merkle_hash::MerkleHash;
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+use crate::node_store::NodeStore;
+
+/// The `SubtreeProof` struct proves that the root of a complete subtree is the node at a given level and index of a tree.
+///
+/// It is a proof of inclusion for an inner node: the siblings of the node and of its ancestors, from its level to the root, whose sides follow from the bits of the index. The size in the proof is the one of the tree it was made from, but nothing in the proof authenticates it, so it is verified against the size and root of a trusted tree, and a proof for a node of another level doesn't pass.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubtreeProof {
+    pub size: usize,
+    pub level: usize,
+    pub index: usize,
+    pub path: Vec<MerkleHash>,
+}
+
+/// Returns the index after the last leaf under the node at the given level and index, or `None` if it overflows.
+fn subtree_end(level: usize, index: usize) -> Option<usize> {
+    let width = 1usize.checked_shl(level as u32)?;
+    index.checked_add(1)?.checked_mul(width)
+}
+
+impl<S: NodeStore> MerkleTree<S> {
+    /// Returns the indices of the leaves under the node at the given level and index, which must be the root of a complete subtree.
+    fn subtree_leaves(&self, level: usize, index: usize) -> Result<Range<usize>, MerkleTreeError> {
+        match subtree_end(level, index) {
+            Some(end) if end <= self.len() => Ok(end - (1 << level)..end),
+            _ => Err(MerkleTreeError::InvalidValue(format!(
+                "The node at level {} and index {} is not the root of a complete subtree of a tree of {} leaves",
+                level,
+                index,
+                self.len()
+            ))),
+        }
+    }
+
+    /// Returns the subtree under the node at the given level and index as its own tree, with 2^level leaves.
+    /// Its nodes are copied from the tree, so nothing is hashed again.
+    ///
+    /// # Parameters
+    /// - `level`: The level of the root of the subtree, 0 being the level of the leaves
+    /// - `index`: The index of the root of the subtree in its level
+    pub fn subtree(&self, level: usize, index: usize) -> Result<MerkleTree, MerkleTreeError> {
+        let leaves = self.subtree_leaves(level, index)?;
+        let mut store = MemoryStore::new();
+        store.set_leaf_count(leaves.len())?;
+        for depth in 0..=level {
+            let nodes =
+                self.store()
+                    .get_batch(depth, leaves.start >> depth, leaves.len() >> depth)?;
+            store.put_batch(depth, 0, &nodes)?;
+        }
+        Ok(MerkleTree::from_store(store))
+    }
+
+    /// Returns the proof that the root of the subtree at the given level and index is in the tree.
+    ///
+    /// # Parameters
+    /// - `level`: The level of the root of the subtree, 0 being the level of the leaves
+    /// - `index`: The index of the root of the subtree in its level
+    pub fn subtree_proof(
+        &self,
+        level: usize,
+        index: usize,
+    ) -> Result<SubtreeProof, MerkleTreeError> {
+        self.subtree_leaves(level, index)?;
+        let mut path = vec![];
+        let mut position = index;
+        for depth in level..self.height() - 1 {
+            let sibling = match position % 2 {
+                1 => position - 1,
+                _ => (position + 1).min(self.level_len(depth) - 1),
+            };
+            path.push(MerkleTree::digest_to_hex(&self.digest(depth, sibling)?));
+            position /= 2;
+        }
+
+        Ok(SubtreeProof {
+            size: self.len(),
+            level,
+            index,
+            path,
+        })
+    }
+}
+
+impl SubtreeProof {
+    /// Returns the indices of the leaves of the subtree in the tree, or `None` if they don't fit in a `usize`.
+    pub fn leaves(&self) -> Option<Range<usize>> {
+        let end = subtree_end(self.level, self.index)?;
+        Some(end - (1 << self.level)..end)
+    }
+
+    /// Verifies that a subtree root is the node at the level and index of the proof in a tree with the given size and root.
+    ///
+    /// # Parameters
+    /// - `subtree_root`: The root of the subtree, e.g. the root of the tree returned by `MerkleTree::subtree`
+    /// - `size`: The trusted number of leaves of the whole tree
+    /// - `root`: The trusted root of the whole tree
+    pub fn verify(&self, subtree_root: &MerkleHash, size: usize, root: &MerkleHash) -> bool {
+        if self.size != size || subtree_end(self.level, self.index).is_none_or(|end| end > size) {
+            return false;
+        }
+        let height = MerkleTree::height_of(self.size);
+        if self.path.len() != height - 1 - self.level {
+            return false;
+        }
+
+        let mut node = subtree_root.clone();
+        for (depth, sibling) in self.path.iter().enumerate() {
+            node = match (self.index >> depth) % 2 {
+                1 => MerkleTree::combine_hashes(sibling, &node),
+                _ => MerkleTree::combine_hashes(&node, sibling),
+            };
+        }
+        node == *root
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::merkle_hash::MerkleHash;
+    use crate::merkle_tree::MerkleTree;
+
+    #[test]
+    fn subtrees_are_extracted_and_proved() {
+        let hashes: Vec<MerkleHash> = (0..37)
+            .map(|i| MerkleTree::get_hash_of(&format!("leaf{}", i)))
+            .collect();
+        let tree = MerkleTree::new_from_hashes(hashes.clone()).unwrap();
+        let root = tree.root().unwrap();
+        let forged = MerkleTree::get_hash_of(&"forged");
+
+        for level in 0..tree.height() - 1 {
+            for index in 0..tree.len() >> level {
+                let subtree = tree.subtree(level, index).unwrap();
+                let proof = tree.subtree_proof(level, index).unwrap();
+                let leaves = proof.leaves().unwrap();
+                let expected = MerkleTree::new_from_hashes(hashes[leaves].to_vec()).unwrap();
+
+                let subtree_root = subtree.root().unwrap();
+                assert_eq!(subtree_root, expected.root().unwrap());
+                assert_eq!(Some(subtree_root.clone()), tree.node(level, index));
+                assert!(proof.verify(&subtree_root, 37, &root));
+                assert!(!proof.verify(&forged, 37, &root));
+            }
+        }
+
+        let mut proof = tree.subtree_proof(3, 2).unwrap();
+        let subtree_root = tree.node(3, 2).unwrap();
+        proof.index = 3;
+        assert!(!proof.verify(&subtree_root, 37, &root));
+        proof.index = 2;
+        proof.level = 2;
+        assert!(!proof.verify(&subtree_root, 37, &root));
+        proof.index = usize::MAX;
+        assert_eq!(proof.leaves(), None);
+        assert!(!proof.verify(&subtree_root, 37, &root));
+
+        // The last node of level 1 of a tree of 5 leaves is hashed with itself, so it can claim a copy of itself past the end.
+        let five = MerkleTree::new_from_hashes(hashes[..5].to_vec()).unwrap();
+        let mut proof = five.subtree_proof(0, 4).unwrap();
+        proof.level = 1;
+        proof.index = 2;
+        proof.path.remove(0);
+        proof.size = 6;
+        let node = five.node(1, 2).unwrap();
+        assert!(proof.verify(&node, 6, &five.root().unwrap()));
+        assert!(!proof.verify(&node, 5, &five.root().unwrap()));
+
+        assert!(tree.subtree(3, 4).is_err());
+        assert!(tree.subtree_proof(2, 9).is_err());
+        assert!(tree.subtree(6, 0).is_err());
+    }
+}
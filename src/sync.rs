@@ -0,0 +1,507 @@
+use std::collections::BTreeMap;
+use std::io::{ErrorKind, Read, Write};
+use std::ops::Range;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::merkle_hash::{MerkleDigest, MerkleHash};
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+use crate::node_store::NodeStore;
+
+/// Largest message accepted, to avoid allocating huge buffers for corrupted lengths.
+const MAX_MESSAGE_LEN: usize = 64 * 1024 * 1024;
+
+/// Number of leaves requested at once.
+const LEAVES_PER_REQUEST: usize = 1 << 14;
+
+/// A request sent by the replica that pulls.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Request {
+    Info,
+    Nodes { level: usize, indices: Vec<usize> },
+    Leaves { start: usize, end: usize },
+    Done,
+}
+
+/// A response of the replica that serves.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Response {
+    Info {
+        len: usize,
+        root: Option<MerkleHash>,
+    },
+    Nodes {
+        hashes: Vec<MerkleHash>,
+    },
+    Leaves {
+        hashes: Vec<MerkleHash>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// What a replica learned and transferred while pulling from another one.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SyncReport {
+    /// Ranges of leaf indices that differed, including the leaves the other replica has after the last local one.
+    pub differing: Vec<Range<usize>>,
+    /// Number of leaves transferred.
+    pub transferred: usize,
+    /// Number of request and response round trips.
+    pub round_trips: usize,
+    /// Number of leaves of the synchronized tree.
+    pub len: usize,
+}
+
+/// Writes a message as a 4 byte little endian length followed by its JSON.
+fn send(stream: &mut impl Write, message: &impl Serialize) -> Result<(), MerkleTreeError> {
+    let json = serde_json::to_vec(message)
+        .map_err(|e| MerkleTreeError::FailedToWriteFile(e.to_string()))?;
+    stream
+        .write_all(&(json.len() as u32).to_le_bytes())
+        .and_then(|_| stream.write_all(&json))
+        .and_then(|_| stream.flush())
+        .map_err(|e| MerkleTreeError::FailedToWriteFile(e.to_string()))
+}
+
+/// Reads a message written by `send`, or returns None if the stream ended before it.
+fn receive<T: DeserializeOwned>(stream: &mut impl Read) -> Result<Option<T>, MerkleTreeError> {
+    let mut len = [0u8; 4];
+    match stream.read_exact(&mut len) {
+        Ok(_) => (),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(MerkleTreeError::FailedToProcessFile(e.to_string())),
+    }
+
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_MESSAGE_LEN {
+        return Err(MerkleTreeError::FailedToProcessFile(format!(
+            "Message of {} bytes is too long",
+            len
+        )));
+    }
+    let mut json = vec![0u8; len];
+    stream
+        .read_exact(&mut json)
+        .map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))?;
+    serde_json::from_slice(&json)
+        .map(Some)
+        .map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))
+}
+
+/// Sends a request and waits for its response.
+fn call(stream: &mut (impl Read + Write), request: &Request) -> Result<Response, MerkleTreeError> {
+    send(stream, request)?;
+    match receive(stream)? {
+        Some(Response::Error { message }) => Err(MerkleTreeError::InvalidValue(message)),
+        Some(response) => Ok(response),
+        None => Err(MerkleTreeError::FailedToProcessFile(
+            "The other replica closed the connection".to_string(),
+        )),
+    }
+}
+
+fn hashes_to_digests(hashes: &[MerkleHash]) -> Result<Vec<MerkleDigest>, MerkleTreeError> {
+    hashes
+        .iter()
+        .map(|hash| MerkleTree::hex_to_digest(hash))
+        .collect()
+}
+
+/// Returns the number of nodes of a level of a tree with the given number of leaves.
+fn level_len(len: usize, level: usize) -> usize {
+    match level < MerkleTree::height_of(len) {
+        true => len.div_ceil(1 << level),
+        false => 0,
+    }
+}
+
+/// Adds a range after the last one, merging them if they are adjacent.
+fn push_range(ranges: &mut Vec<Range<usize>>, range: Range<usize>) {
+    match ranges.last_mut() {
+        Some(last) if last.end == range.start => last.end = range.end,
+        _ => ranges.push(range),
+    }
+}
+
+/// Answers the requests of a replica that pulls from this one, until it is done or closes the stream.
+///
+/// # Parameters
+/// - `stream`: The connection with the other replica
+/// - `tree`: The tree to serve
+pub fn serve<S: NodeStore>(
+    stream: &mut (impl Read + Write),
+    tree: &MerkleTree<S>,
+) -> Result<(), MerkleTreeError> {
+    while let Some(request) = receive::<Request>(stream)? {
+        let response = match request {
+            Request::Done => return Ok(()),
+            Request::Info => Ok(Response::Info {
+                len: tree.len(),
+                root: tree.root().ok(),
+            }),
+            Request::Nodes { level, indices } => indices
+                .iter()
+                .map(|&index| tree.digest(level, index))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|digests| Response::Nodes {
+                    hashes: digests.iter().map(MerkleTree::digest_to_hex).collect(),
+                }),
+            Request::Leaves { start, end } => tree
+                .store()
+                .get_batch(0, start, end.saturating_sub(start))
+                .map(|digests| Response::Leaves {
+                    hashes: digests.iter().map(MerkleTree::digest_to_hex).collect(),
+                }),
+        };
+
+        let response = response.unwrap_or_else(|e| Response::Error {
+            message: format!("{:?}", e),
+        });
+        send(stream, &response)?;
+    }
+    Ok(())
+}
+
+/// Makes a tree equal to the one of another replica, which must be running `serve` on the other end of the stream.
+///
+/// Both replicas compare their trees level by level, from the root down, with one round trip per level: only the children of the nodes that differ are requested, so finding k differing leaves costs O(k log n) hashes. When the trees have different lengths, the nodes on the right edge of the shorter one are treated as differing without being compared, as the last node of a level is hashed with itself and `[a, b, c]` has the same root as `[a, b, c, c]`. Then only the differing leaves are transferred, and the length and the root the tree would have are checked against the remote ones before anything is changed, so a failed pull leaves the tree as it was.
+///
+/// # Parameters
+/// - `stream`: The connection with the other replica
+/// - `tree`: The tree to update
+pub fn pull<S: NodeStore>(
+    stream: &mut (impl Read + Write),
+    tree: &mut MerkleTree<S>,
+) -> Result<SyncReport, MerkleTreeError> {
+    let mut round_trips = 1;
+    let (remote_len, remote_root) = match call(stream, &Request::Info)? {
+        Response::Info { len, root } => (len, root),
+        _ => return Err(unexpected_response()),
+    };
+
+    let local_len = tree.len();
+    let shorter = local_len.min(remote_len);
+    let mut differing = vec![];
+    let mut frontier = vec![0usize];
+
+    for level in (0..tree.height().max(MerkleTree::height_of(remote_len))).rev() {
+        // A node covering leaves past the end of the shorter tree is not the same node in both trees, even if its digest is.
+        let on_edge = |index: usize| local_len != remote_len && ((index + 1) << level) > shorter;
+        let mut compare = vec![];
+        for &index in frontier.iter() {
+            let in_local = index < tree.level_len(level);
+            let in_remote = index < level_len(remote_len, level);
+            match (in_local, in_remote) {
+                (false, false) => (),
+                (true, true) => compare.push(index),
+                _ if (index << level) >= shorter => {
+                    if in_remote {
+                        let end = ((index + 1) << level).min(remote_len);
+                        push_range(&mut differing, (index << level)..end);
+                    }
+                }
+                _ => compare.push(index),
+            }
+        }
+
+        let mut next = vec![];
+        let to_ask: Vec<usize> = compare
+            .iter()
+            .copied()
+            .filter(|&index| {
+                index < level_len(remote_len, level)
+                    && index < tree.level_len(level)
+                    && !on_edge(index)
+            })
+            .collect();
+        let remote = match to_ask.is_empty() {
+            true => vec![],
+            false => {
+                round_trips += 1;
+                match call(
+                    stream,
+                    &Request::Nodes {
+                        level,
+                        indices: to_ask.clone(),
+                    },
+                )? {
+                    Response::Nodes { hashes } if hashes.len() == to_ask.len() => {
+                        hashes_to_digests(&hashes)?
+                    }
+                    _ => return Err(unexpected_response()),
+                }
+            }
+        };
+
+        let mut asked = to_ask.iter().zip(remote.iter()).peekable();
+        for index in compare {
+            let differs = match asked.peek() {
+                Some((&asked_index, remote_digest)) if asked_index == index => {
+                    let differs = tree.digest(level, index)? != **remote_digest;
+                    asked.next();
+                    differs
+                }
+                _ => true,
+            };
+            if !differs {
+                continue;
+            }
+            match level {
+                0 if index < remote_len => push_range(&mut differing, index..index + 1),
+                0 => (),
+                _ => next.extend([2 * index, 2 * index + 1]),
+            }
+        }
+        frontier = next;
+    }
+
+    differing.sort_by_key(|range| range.start);
+    let differing = differing.into_iter().fold(vec![], |mut ranges, range| {
+        push_range(&mut ranges, range);
+        ranges
+    });
+    let Fetched { updates, appended } = fetch(stream, &differing, shorter, &mut round_trips)?;
+    send(stream, &Request::Done)?;
+
+    if shorter + appended.len() != remote_len {
+        return Err(MerkleTreeError::InvalidValue(format!(
+            "The synchronized tree would have {} leaves, but the other replica has {}",
+            shorter + appended.len(),
+            remote_len
+        )));
+    }
+    let root = root_after(tree, &updates, &appended, remote_len)?;
+    if root.as_ref().map(MerkleTree::digest_to_hex) != remote_root {
+        return Err(MerkleTreeError::InvalidHash(
+            "The synchronized tree doesn't match the root of the other replica".to_string(),
+        ));
+    }
+
+    let transferred = updates.len() + appended.len();
+    tree.truncate(remote_len)?;
+    tree.update_digests(&updates)?;
+    tree.append_digests(appended)?;
+
+    Ok(SyncReport {
+        differing,
+        transferred,
+        round_trips,
+        len: tree.len(),
+    })
+}
+
+/// The leaves fetched from the other replica.
+struct Fetched {
+    /// The leaves that replace local ones, with their indices.
+    updates: Vec<(usize, MerkleDigest)>,
+    /// The leaves to append after the local leaves that are kept.
+    appended: Vec<MerkleDigest>,
+}
+
+/// Fetches the differing leaves, splitting them between the ones that replace the first `kept` leaves and the ones to append after them.
+fn fetch(
+    stream: &mut (impl Read + Write),
+    differing: &[Range<usize>],
+    kept: usize,
+    round_trips: &mut usize,
+) -> Result<Fetched, MerkleTreeError> {
+    let mut updates = vec![];
+    let mut appended = vec![];
+    for range in differing {
+        let mut start = range.start;
+        while start < range.end {
+            let end = (start + LEAVES_PER_REQUEST).min(range.end);
+            *round_trips += 1;
+            let digests = match call(stream, &Request::Leaves { start, end })? {
+                Response::Leaves { hashes } if hashes.len() == end - start => {
+                    hashes_to_digests(&hashes)?
+                }
+                _ => return Err(unexpected_response()),
+            };
+
+            for (i, digest) in digests.into_iter().enumerate() {
+                match start + i < kept {
+                    true => updates.push((start + i, digest)),
+                    false => appended.push(digest),
+                }
+            }
+            start = end;
+        }
+    }
+    Ok(Fetched { updates, appended })
+}
+
+/// Returns the root the tree would have with `len` leaves, after replacing some of its leaves and appending others after the ones it keeps, without changing it.
+fn root_after<S: NodeStore>(
+    tree: &MerkleTree<S>,
+    updates: &[(usize, MerkleDigest)],
+    appended: &[MerkleDigest],
+    len: usize,
+) -> Result<Option<MerkleDigest>, MerkleTreeError> {
+    let kept = tree.len().min(len);
+    let mut changed: BTreeMap<usize, MerkleDigest> = updates
+        .iter()
+        .copied()
+        .chain((kept..).zip(appended.iter().copied()))
+        .collect();
+    let node = |changed: &BTreeMap<usize, MerkleDigest>, level: usize, index: usize| match changed
+        .get(&index)
+    {
+        Some(digest) => Ok(*digest),
+        None => tree.digest(level, index),
+    };
+
+    let height = MerkleTree::height_of(len);
+    for level in 1..height {
+        let below = level_len(len, level - 1);
+        let mut parents: Vec<usize> = changed.keys().map(|index| index / 2).collect();
+        if len != tree.len() {
+            // The last node of the level covered leaves that are dropped or appended.
+            parents.push(level_len(len, level) - 1);
+        }
+        parents.sort_unstable();
+        parents.dedup();
+
+        let mut next = BTreeMap::new();
+        for parent in parents {
+            let left = node(&changed, level - 1, 2 * parent)?;
+            let right = match 2 * parent + 1 < below {
+                true => node(&changed, level - 1, 2 * parent + 1)?,
+                false => left,
+            };
+            next.insert(parent, MerkleTree::combine(&left, &right));
+        }
+        changed = next;
+    }
+
+    match height {
+        0 => Ok(None),
+        _ => node(&changed, height - 1, 0).map(Some),
+    }
+}
+
+fn unexpected_response() -> MerkleTreeError {
+    MerkleTreeError::FailedToProcessFile("Unexpected response from the other replica".to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    use crate::merkle_hash::MerkleHash;
+    use crate::merkle_tree::{test_hashes, MerkleTree};
+    use crate::sync::{pull, receive, send, serve, Request, Response};
+
+    fn sync(
+        remote: Vec<MerkleHash>,
+        local: Vec<MerkleHash>,
+    ) -> (MerkleTree, crate::sync::SyncReport) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let tree = MerkleTree::new_from_hashes(remote).unwrap();
+            let (mut stream, _) = listener.accept().unwrap();
+            serve(&mut stream, &tree).unwrap();
+        });
+
+        let mut tree = MerkleTree::new_from_hashes(local).unwrap();
+        let mut stream = TcpStream::connect(address).unwrap();
+        let report = pull(&mut stream, &mut tree).unwrap();
+        server.join().unwrap();
+        (tree, report)
+    }
+
+    #[test]
+    fn replicas_converge_transferring_only_the_differing_leaves() {
+        let remote = test_hashes("leaf", 5000);
+        let mut local = remote.clone();
+        local[10] = MerkleTree::get_hash_of(&"drifted");
+        local[4321] = MerkleTree::get_hash_of(&"drifted too");
+        local.truncate(4990);
+
+        let expected = MerkleTree::new_from_hashes(remote.clone()).unwrap();
+        let (tree, report) = sync(remote, local);
+
+        assert_eq!(tree.root().unwrap(), expected.root().unwrap());
+        assert_eq!(report.differing, vec![10..11, 4321..4322, 4990..5000]);
+        assert_eq!(report.transferred, 12);
+        assert_eq!(report.len, 5000);
+    }
+
+    #[test]
+    fn longer_and_equal_replicas_are_synchronized() {
+        let remote = test_hashes("leaf", 100);
+        let mut local = remote.clone();
+        local.extend(test_hashes("extra", 60));
+        local[3] = MerkleTree::get_hash_of(&"drifted");
+
+        let expected = MerkleTree::new_from_hashes(remote.clone()).unwrap();
+        let (tree, report) = sync(remote.clone(), local);
+        assert_eq!(tree.root().unwrap(), expected.root().unwrap());
+        assert_eq!(report.differing, vec![3..4]);
+
+        let (tree, report) = sync(remote.clone(), remote);
+        assert_eq!(tree.root().unwrap(), expected.root().unwrap());
+        assert_eq!(report.transferred, 0);
+        assert_eq!(report.round_trips, 2);
+    }
+
+    #[test]
+    fn duplicated_last_leaves_are_synchronized() {
+        let mut leaves = test_hashes("leaf", 4);
+        leaves[3] = leaves[2].clone();
+        let short = leaves[..3].to_vec();
+        assert_eq!(
+            MerkleTree::new_from_hashes(leaves.clone())
+                .unwrap()
+                .root()
+                .unwrap(),
+            MerkleTree::new_from_hashes(short.clone())
+                .unwrap()
+                .root()
+                .unwrap()
+        );
+
+        let (tree, report) = sync(leaves.clone(), short.clone());
+        assert_eq!(report.differing, vec![3..4]);
+        assert_eq!(report.len, 4);
+        assert_eq!(tree.len(), 4);
+
+        let (tree, report) = sync(short, leaves);
+        assert_eq!(report.len, 3);
+        assert_eq!(tree.len(), 3);
+    }
+
+    #[test]
+    fn failed_pulls_leave_the_tree_unchanged() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let tree = MerkleTree::new_from_hashes(test_hashes("remote", 50)).unwrap();
+            let (mut stream, _) = listener.accept().unwrap();
+            let _: Request = receive(&mut stream).unwrap().unwrap();
+            let info = Response::Info {
+                len: tree.len(),
+                root: Some(MerkleTree::get_hash_of(&"forged")),
+            };
+            send(&mut stream, &info).unwrap();
+            serve(&mut stream, &tree).unwrap();
+        });
+
+        let mut tree = MerkleTree::new_from_hashes(test_hashes("local", 40)).unwrap();
+        let root = tree.root().unwrap();
+        let mut stream = TcpStream::connect(address).unwrap();
+        assert!(pull(&mut stream, &mut tree).is_err());
+        server.join().unwrap();
+
+        assert_eq!(tree.len(), 40);
+        assert_eq!(tree.root().unwrap(), root);
+    }
+}
@@ -0,0 +1,658 @@
+use std::path::{Path, PathBuf};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+use crate::node_store::NodeStore;
+
+/// The height of the tiles: every tile holds up to 2^8 hashes of one level of the tree.
+pub const TILE_HEIGHT: usize = 8;
+
+/// The number of hashes of a full tile.
+const TILE_WIDTH: u64 = 1 << TILE_HEIGHT;
+
+/// The name of the file with the checkpoint note, at the root of the tile directory.
+const CHECKPOINT_FILE: &str = "checkpoint";
+
+/// A SHA-256 hash of a tree in `TreeMode::Rfc6962`.
+pub type TlogHash = [u8; 32];
+
+/// The `Checkpoint` struct is the signed head of a tiled log: its origin, size and RFC 6962 root, written as a signed note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub origin: String,
+    pub size: u64,
+    pub root: TlogHash,
+}
+
+/// The `TileReader` struct reads the tiles written by `export_tiles` from a directory and rebuilds the hashes, roots and proofs of the log from them, as a client of a static log would.
+///
+/// Tiles are read from disk every time a hash is needed, so a reader is cheap to create and never holds more than one tile.
+#[derive(Debug)]
+pub struct TileReader {
+    dir: PathBuf,
+    checkpoint: Checkpoint,
+}
+
+/// Returns the RFC 6962 hash of a leaf: `SHA-256(0x00 || data)`.
+pub fn leaf_hash(data: &[u8]) -> TlogHash {
+    let mut hasher = Sha256::new();
+    hasher.update([0u8]);
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+/// Returns the RFC 6962 hash of a node: `SHA-256(0x01 || left || right)`.
+pub fn node_hash(left: &TlogHash, right: &TlogHash) -> TlogHash {
+    let mut hasher = Sha256::new();
+    hasher.update([1u8]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Returns the largest power of two smaller than `n`, which must be at least 2.
+fn split_point(n: u64) -> u64 {
+    1 << (63 - (n - 1).leading_zeros())
+}
+
+/// Returns the path of a tile, relative to the tile directory, e.g. `tile/8/0/x001/234` or `tile/8/1/005.p/17` for a partial tile of 17 hashes.
+///
+/// # Parameters
+/// - `level`: The level of the tile, whose hashes are at level `level * TILE_HEIGHT` of the tree
+/// - `index`: The index of the tile in its level
+/// - `width`: The number of hashes of the tile
+pub fn tile_path(level: usize, index: u64, width: u64) -> PathBuf {
+    let mut parts = vec![format!("{:03}", index % 1000)];
+    let mut rest = index / 1000;
+    while rest > 0 {
+        parts.push(format!("x{:03}", rest % 1000));
+        rest /= 1000;
+    }
+    parts.reverse();
+
+    let mut path = PathBuf::from(format!("tile/{}/{}", TILE_HEIGHT, level));
+    let last = parts.pop().unwrap_or_default();
+    for part in parts {
+        path.push(part);
+    }
+    match width == TILE_WIDTH {
+        true => path.push(last),
+        false => path.push(format!("{}.p/{}", last, width)),
+    }
+    path
+}
+
+/// Returns the RFC 6962 hash of the leaves `start..end`, reading the hashes of complete subtrees with `stored`, which takes a level and an index.
+fn subtree_hash(
+    start: u64,
+    end: u64,
+    stored: &mut impl FnMut(usize, u64) -> Result<TlogHash, MerkleTreeError>,
+) -> Result<TlogHash, MerkleTreeError> {
+    let size = end - start;
+    if size.is_power_of_two() && start.is_multiple_of(size) {
+        let level = size.trailing_zeros() as usize;
+        return stored(level, start >> level);
+    }
+    let k = split_point(size);
+    let left = subtree_hash(start, start + k, stored)?;
+    let right = subtree_hash(start + k, end, stored)?;
+    Ok(node_hash(&left, &right))
+}
+
+/// Returns the key hash of a signed note: the first 4 bytes of `SHA-256(name || "\n" || 0x01 || public key)`.
+fn note_key_hash(name: &str, key: &VerifyingKey) -> [u8; 4] {
+    let mut hasher = Sha256::new();
+    hasher.update(name.as_bytes());
+    hasher.update(b"\n\x01");
+    hasher.update(key.as_bytes());
+    let hash = hasher.finalize();
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+impl Checkpoint {
+    /// Returns the text of the note: the origin, the size and the root in base64, one per line.
+    pub fn body(&self) -> String {
+        format!(
+            "{}\n{}\n{}\n",
+            self.origin,
+            self.size,
+            STANDARD.encode(self.root)
+        )
+    }
+
+    /// Returns the checkpoint as a note signed with the key of the log, named after its origin.
+    pub fn sign(&self, key: &SigningKey) -> String {
+        let body = self.body();
+        let mut signature = note_key_hash(&self.origin, &key.verifying_key()).to_vec();
+        signature.extend(key.sign(body.as_bytes()).to_bytes());
+        format!(
+            "{}\n\u{2014} {} {}\n",
+            body,
+            self.origin,
+            STANDARD.encode(signature)
+        )
+    }
+
+    /// Parses a signed note, checking that it is signed by the given key under the name of its origin.
+    pub fn from_note(note: &str, key: &VerifyingKey) -> Result<Checkpoint, MerkleTreeError> {
+        let invalid =
+            |reason: &str| MerkleTreeError::InvalidValue(format!("Invalid note: {}", reason));
+        let (body, signatures) = note
+            .split_once("\n\n")
+            .ok_or_else(|| invalid("no signatures"))?;
+        let body = format!("{}\n", body);
+
+        let lines: Vec<&str> = body.lines().collect();
+        if lines.len() < 3 {
+            return Err(invalid("the checkpoint needs an origin, a size and a root"));
+        }
+        let root: TlogHash = STANDARD
+            .decode(lines[2])
+            .ok()
+            .and_then(|root| root.try_into().ok())
+            .ok_or_else(|| invalid("the root is not 32 bytes in base64"))?;
+        let checkpoint = Checkpoint {
+            origin: lines[0].to_string(),
+            size: lines[1]
+                .parse()
+                .map_err(|_| invalid("the size is not a number"))?,
+            root,
+        };
+
+        let key_hash = note_key_hash(&checkpoint.origin, key);
+        for line in signatures.lines() {
+            let signature = line
+                .strip_prefix("\u{2014} ")
+                .and_then(|line| line.strip_prefix(checkpoint.origin.as_str()))
+                .and_then(|line| line.strip_prefix(' '))
+                .and_then(|signature| STANDARD.decode(signature).ok());
+            let signature = match signature {
+                Some(signature) if signature.len() == 68 && signature[..4] == key_hash => signature,
+                _ => continue,
+            };
+            let signature: [u8; 64] = signature[4..]
+                .try_into()
+                .map_err(|_| invalid("bad signature"))?;
+            return key
+                .verify(body.as_bytes(), &Signature::from_bytes(&signature))
+                .map(|_| checkpoint)
+                .map_err(|_| {
+                    MerkleTreeError::InvalidSignature(
+                        "The checkpoint was not signed with the key".to_string(),
+                    )
+                });
+        }
+        Err(MerkleTreeError::InvalidSignature(
+            "The checkpoint has no signature of the key".to_string(),
+        ))
+    }
+}
+
+/// Writes the tiles of a tree in `TreeMode::Rfc6962` to a directory, with a checkpoint note signed with the key of the log, and returns the checkpoint.
+///
+/// Every leaf of the tree is a record of the log, whose RFC 6962 leaf hash is `SHA-256(0x00 || leaf)`, the leaf being its 32 bytes. Full tiles never change, so the ones already in the directory are not written again, but they are checked against the hashes of the tree, and the last tile of every level is written as a partial tile. A directory can so be exported to again after appending to the tree, and clients holding older checkpoints can still read it. The root is computed from the tree and checked against the root read back from the tiles before the checkpoint is signed.
+///
+/// # Parameters
+/// - `tree`: The tree to export
+/// - `dir`: The directory of the tiles
+/// - `origin`: The name of the log, first line of the checkpoint
+/// - `key`: The key of the log, used to sign the checkpoint
+pub fn export_tiles<S: NodeStore>(
+    tree: &MerkleTree<S>,
+    dir: impl AsRef<Path>,
+    origin: &str,
+    key: &SigningKey,
+) -> Result<Checkpoint, MerkleTreeError> {
+    let dir = dir.as_ref();
+    let size = tree.len() as u64;
+    let mut reader = TileReader {
+        dir: dir.to_path_buf(),
+        checkpoint: Checkpoint {
+            origin: origin.to_string(),
+            size,
+            root: Sha256::digest([]).into(),
+        },
+    };
+
+    let mut row = (0..size)
+        .map(|i| Ok(leaf_hash(&tree.digest(0, i as usize)?)))
+        .collect::<Result<Vec<_>, MerkleTreeError>>()?;
+    if size > 0 {
+        reader.checkpoint.root = subtree_hash(0, size, &mut |level, index| {
+            let start = (index << level) as usize;
+            Ok(fold_tile(row[start..start + (1 << level)].to_vec()))
+        })?;
+    }
+
+    // Every full tile already in the directory is checked before anything is written.
+    let mut to_write = vec![];
+    let mut tile_level = 0;
+    while !row.is_empty() {
+        for (index, hashes) in row.chunks(TILE_WIDTH as usize).enumerate() {
+            let index = index as u64;
+            let path = dir.join(tile_path(tile_level, index, hashes.len() as u64));
+            if hashes.len() as u64 == TILE_WIDTH && path.exists() {
+                if reader.read_tile(tile_level, index)? != hashes {
+                    return Err(MerkleTreeError::InvalidHash(format!(
+                        "The tile {:?} is not a tile of the tree",
+                        path
+                    )));
+                }
+                continue;
+            }
+            to_write.push((path, hashes.concat()));
+        }
+        row = row
+            .chunks_exact(TILE_WIDTH as usize)
+            .map(|tile| fold_tile(tile.to_vec()))
+            .collect();
+        tile_level += 1;
+    }
+    for (path, contents) in to_write {
+        write_file(&path, &contents)?;
+    }
+
+    if size > 0 && reader.subtree(0, size)? != reader.checkpoint.root {
+        return Err(MerkleTreeError::InvalidHash(
+            "The tiles don't give the root of the tree".to_string(),
+        ));
+    }
+    write_file(
+        &dir.join(CHECKPOINT_FILE),
+        reader.checkpoint.sign(key).as_bytes(),
+    )?;
+    Ok(reader.checkpoint)
+}
+
+/// Returns the root of the complete subtree whose leaves are the hashes of a tile, or of part of one with a power of two hashes.
+fn fold_tile(mut hashes: Vec<TlogHash>) -> TlogHash {
+    while hashes.len() > 1 {
+        hashes = hashes
+            .chunks_exact(2)
+            .map(|pair| node_hash(&pair[0], &pair[1]))
+            .collect();
+    }
+    hashes[0]
+}
+
+fn write_file(path: &Path, contents: &[u8]) -> Result<(), MerkleTreeError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| MerkleTreeError::FailedToWriteFile(e.to_string()))?;
+    }
+    std::fs::write(path, contents).map_err(|e| MerkleTreeError::FailedToWriteFile(e.to_string()))
+}
+
+impl TileReader {
+    /// Opens a tile directory, checking its checkpoint with the key of the log.
+    pub fn open(dir: impl AsRef<Path>, key: &VerifyingKey) -> Result<TileReader, MerkleTreeError> {
+        let dir = dir.as_ref().to_path_buf();
+        let note = std::fs::read_to_string(dir.join(CHECKPOINT_FILE))
+            .map_err(|e| MerkleTreeError::FailedToProcessFile(e.to_string()))?;
+        let checkpoint = Checkpoint::from_note(&note, key)?;
+        Ok(TileReader { dir, checkpoint })
+    }
+
+    /// Returns the checkpoint of the log.
+    pub fn checkpoint(&self) -> &Checkpoint {
+        &self.checkpoint
+    }
+
+    /// Returns the hashes of a tile of the tree of the checkpoint.
+    fn read_tile(&self, level: usize, index: u64) -> Result<Vec<TlogHash>, MerkleTreeError> {
+        let width = u32::try_from(level * TILE_HEIGHT)
+            .ok()
+            .and_then(|shift| self.checkpoint.size.checked_shr(shift))
+            .zip(index.checked_mul(TILE_WIDTH))
+            .and_then(|(row_len, first)| row_len.checked_sub(first))
+            .filter(|&width| width > 0)
+            .ok_or_else(|| {
+                MerkleTreeError::InvalidValue(format!(
+                    "There is no tile at level {} and index {}",
+                    level, index
+                ))
+            })?
+            .min(TILE_WIDTH);
+        let path = self.dir.join(tile_path(level, index, width));
+        let bytes = std::fs::read(&path)
+            .map_err(|e| MerkleTreeError::StorageError(format!("{:?}: {}", path, e)))?;
+        if bytes.len() as u64 != width * 32 {
+            return Err(MerkleTreeError::StorageError(format!(
+                "The tile {:?} should have {} hashes",
+                path, width
+            )));
+        }
+        Ok(bytes
+            .chunks_exact(32)
+            .map(|hash| hash.try_into().unwrap())
+            .collect())
+    }
+
+    /// Returns the hash of the complete subtree at the given level and index, hashing the hashes of its tile below it.
+    pub fn stored_hash(&self, level: usize, index: u64) -> Result<TlogHash, MerkleTreeError> {
+        let end = u32::try_from(level)
+            .ok()
+            .and_then(|level| 1u64.checked_shl(level))
+            .zip(index.checked_add(1))
+            .and_then(|(width, end)| end.checked_mul(width));
+        if end.is_none_or(|end| end > self.checkpoint.size) {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "There is no complete subtree at level {} and index {}",
+                level, index
+            )));
+        }
+        let tile_level = level / TILE_HEIGHT;
+        let depth = level % TILE_HEIGHT;
+        let start = index << depth;
+        let tile = self.read_tile(tile_level, start / TILE_WIDTH)?;
+
+        let offset = (start % TILE_WIDTH) as usize;
+        Ok(fold_tile(tile[offset..offset + (1 << depth)].to_vec()))
+    }
+
+    /// Checks that a size is between 1 and the size of the checkpoint.
+    fn check_size(&self, size: u64) -> Result<(), MerkleTreeError> {
+        match size > 0 && size <= self.checkpoint.size {
+            true => Ok(()),
+            false => Err(MerkleTreeError::InvalidValue(format!(
+                "The size must be between 1 and {}",
+                self.checkpoint.size
+            ))),
+        }
+    }
+
+    /// Returns the hash of the leaves `start..end`.
+    fn subtree(&self, start: u64, end: u64) -> Result<TlogHash, MerkleTreeError> {
+        subtree_hash(start, end, &mut |level, index| {
+            self.stored_hash(level, index)
+        })
+    }
+
+    /// Returns the root of the tree with the first `size` leaves of the log.
+    pub fn root_at(&self, size: u64) -> Result<TlogHash, MerkleTreeError> {
+        self.check_size(size)?;
+        self.subtree(0, size)
+    }
+
+    /// Returns the RFC 6962 inclusion proof of the leaf at `index` in the tree with the first `size` leaves.
+    pub fn inclusion_proof(&self, index: u64, size: u64) -> Result<Vec<TlogHash>, MerkleTreeError> {
+        self.check_size(size)?;
+        if index >= size {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "There is no leaf at index {} in a tree of {}",
+                index, size
+            )));
+        }
+
+        let mut proof = vec![];
+        let (mut start, mut end, mut index) = (0, size, index);
+        while end - start > 1 {
+            let k = split_point(end - start);
+            if index < k {
+                proof.push(self.subtree(start + k, end)?);
+                end = start + k;
+            } else {
+                proof.push(self.subtree(start, start + k)?);
+                start += k;
+                index -= k;
+            }
+        }
+        proof.reverse();
+        Ok(proof)
+    }
+
+    /// Returns the RFC 6962 consistency proof between the trees with the first `old_size` and `new_size` leaves.
+    pub fn consistency_proof(
+        &self,
+        old_size: u64,
+        new_size: u64,
+    ) -> Result<Vec<TlogHash>, MerkleTreeError> {
+        self.check_size(old_size)?;
+        self.check_size(new_size)?;
+        if old_size > new_size {
+            return Err(MerkleTreeError::InvalidValue(
+                "The old size can't be bigger than the new one".to_string(),
+            ));
+        }
+
+        let mut proof = vec![];
+        let (mut start, mut end, mut m, mut whole) = (0, new_size, old_size, true);
+        while m != end - start {
+            let k = split_point(end - start);
+            if m <= k {
+                proof.push(self.subtree(start + k, end)?);
+                end = start + k;
+            } else {
+                proof.push(self.subtree(start, start + k)?);
+                start += k;
+                m -= k;
+                whole = false;
+            }
+        }
+        if !whole {
+            proof.push(self.subtree(start, end)?);
+        }
+        proof.reverse();
+        Ok(proof)
+    }
+}
+
+/// Verifies an RFC 6962 inclusion proof of a leaf hash at `index` in a tree of `size` leaves with the given root.
+pub fn verify_inclusion(
+    leaf: &TlogHash,
+    index: u64,
+    size: u64,
+    proof: &[TlogHash],
+    root: &TlogHash,
+) -> bool {
+    if index >= size {
+        return false;
+    }
+    let (mut fn_, mut sn, mut hash) = (index, size - 1, *leaf);
+    for sibling in proof {
+        if sn == 0 {
+            return false;
+        }
+        if fn_ & 1 == 1 || fn_ == sn {
+            hash = node_hash(sibling, &hash);
+            while fn_ & 1 == 0 && fn_ != 0 {
+                fn_ >>= 1;
+                sn >>= 1;
+            }
+        } else {
+            hash = node_hash(&hash, sibling);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    sn == 0 && hash == *root
+}
+
+/// Verifies an RFC 6962 consistency proof between a tree of `old_size` leaves and one of `new_size` leaves with the given roots.
+pub fn verify_consistency(
+    old_size: u64,
+    new_size: u64,
+    proof: &[TlogHash],
+    old_root: &TlogHash,
+    new_root: &TlogHash,
+) -> bool {
+    if old_size == 0 || old_size > new_size {
+        return false;
+    }
+    if old_size == new_size {
+        return proof.is_empty() && old_root == new_root;
+    }
+
+    let mut path = proof.to_vec();
+    if old_size.is_power_of_two() {
+        path.insert(0, *old_root);
+    }
+    if path.is_empty() {
+        return false;
+    }
+
+    let (mut fn_, mut sn) = (old_size - 1, new_size - 1);
+    while fn_ & 1 == 1 {
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    let (mut old_hash, mut new_hash) = (path[0], path[0]);
+    for hash in &path[1..] {
+        if sn == 0 {
+            return false;
+        }
+        if fn_ & 1 == 1 || fn_ == sn {
+            old_hash = node_hash(hash, &old_hash);
+            new_hash = node_hash(hash, &new_hash);
+            while fn_ & 1 == 0 && fn_ != 0 {
+                fn_ >>= 1;
+                sn >>= 1;
+            }
+        } else {
+            new_hash = node_hash(&new_hash, hash);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    sn == 0 && old_hash == *old_root && new_hash == *new_root
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::merkle_tree::MerkleTree;
+    use crate::signed_tree_head::generate_key;
+    use crate::tlog_tiles::{
+        export_tiles, leaf_hash, node_hash, split_point, tile_path, verify_consistency,
+        verify_inclusion, TileReader, TlogHash,
+    };
+
+    /// Computes the RFC 6962 root of some leaf hashes, straight from the definition.
+    fn reference_root(leaves: &[TlogHash]) -> TlogHash {
+        match leaves.len() {
+            1 => leaves[0],
+            n => {
+                let k = split_point(n as u64) as usize;
+                node_hash(&reference_root(&leaves[..k]), &reference_root(&leaves[k..]))
+            }
+        }
+    }
+
+    #[test]
+    fn tile_paths_follow_the_tlog_layout() {
+        assert_eq!(tile_path(0, 5, 256), PathBuf::from("tile/8/0/005"));
+        assert_eq!(
+            tile_path(0, 1234067, 256),
+            PathBuf::from("tile/8/0/x001/x234/067")
+        );
+        assert_eq!(tile_path(1, 3, 17), PathBuf::from("tile/8/1/003.p/17"));
+    }
+
+    #[test]
+    fn proofs_are_rebuilt_from_the_tiles() {
+        let dir = std::env::temp_dir().join(format!("merkle-tiles-{}", std::process::id()));
+        let key = generate_key();
+        let elements: Vec<String> = (0..1000).map(|i| format!("leaf{}", i)).collect();
+        let mut tree = MerkleTree::new_from_hashables(elements[..600].to_vec()).unwrap();
+
+        let old = export_tiles(&tree, &dir, "example.com/log", &key).unwrap();
+        tree.append_hashes(
+            elements[600..]
+                .iter()
+                .map(MerkleTree::get_hash_of)
+                .collect::<Vec<_>>(),
+        )
+        .unwrap();
+        let checkpoint = export_tiles(&tree, &dir, "example.com/log", &key).unwrap();
+        assert!(dir.join("tile/8/0/002.p/88").exists());
+        assert!(dir.join("tile/8/0/003.p/232").exists());
+        assert!(dir.join("tile/8/1/000.p/3").exists());
+
+        let leaves: Vec<TlogHash> = (0..1000)
+            .map(|i| leaf_hash(&MerkleTree::hex_to_digest(&tree.node(0, i).unwrap()).unwrap()))
+            .collect();
+        assert_eq!(checkpoint.root, reference_root(&leaves));
+        assert_eq!(old.root, reference_root(&leaves[..600]));
+
+        let reader = TileReader::open(&dir, &key.verifying_key()).unwrap();
+        assert_eq!(reader.checkpoint(), &checkpoint);
+        assert!(TileReader::open(&dir, &generate_key().verifying_key()).is_err());
+
+        for size in [1, 2, 3, 255, 256, 257, 600, 777, 1000] {
+            let root = reader.root_at(size).unwrap();
+            assert_eq!(root, reference_root(&leaves[..size as usize]));
+            for index in [0, size / 3, size - 1] {
+                let proof = reader.inclusion_proof(index, size).unwrap();
+                assert!(verify_inclusion(
+                    &leaves[index as usize],
+                    index,
+                    size,
+                    &proof,
+                    &root
+                ));
+                assert!(!verify_inclusion(
+                    &leaves[index as usize],
+                    index + 1,
+                    size,
+                    &proof,
+                    &root
+                ));
+            }
+            for old_size in [1, 2, 4, 100, 256, 600] {
+                if old_size > size {
+                    continue;
+                }
+                let old_root = reader.root_at(old_size).unwrap();
+                let proof = reader.consistency_proof(old_size, size).unwrap();
+                assert!(verify_consistency(old_size, size, &proof, &old_root, &root));
+                if old_size < size {
+                    assert!(!verify_consistency(old_size, size, &proof, &root, &root));
+                }
+            }
+        }
+        assert!(reader.root_at(1001).is_err());
+        assert!(reader.stored_hash(70, 0).is_err());
+        assert!(reader.stored_hash(0, u64::MAX).is_err());
+        assert!(reader.stored_hash(3, u64::MAX >> 2).is_err());
+        assert!(reader.read_tile(9, 0).is_err());
+        assert!(reader.read_tile(0, u64::MAX).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn full_tiles_of_another_tree_are_rejected() {
+        let dir = std::env::temp_dir().join(format!("merkle-tiles-kept-{}", std::process::id()));
+        let key = generate_key();
+        let leaves = |prefix: &str| (0..600).map(|i| format!("{}{}", prefix, i)).collect();
+        let tree = MerkleTree::new_from_hashables(leaves("leaf")).unwrap();
+        let checkpoint = export_tiles(&tree, &dir, "example.com/log", &key).unwrap();
+        assert_eq!(
+            export_tiles(&tree, &dir, "example.com/log", &key).unwrap(),
+            checkpoint
+        );
+
+        let other = MerkleTree::new_from_hashables(leaves("other")).unwrap();
+        assert!(export_tiles(&other, &dir, "example.com/log", &key).is_err());
+        assert_eq!(
+            export_tiles(&tree, &dir, "example.com/log", &key).unwrap(),
+            checkpoint
+        );
+
+        let first_tile = dir.join(tile_path(0, 0, 256));
+        let mut bytes = std::fs::read(&first_tile).unwrap();
+        bytes[0] ^= 1;
+        std::fs::write(&first_tile, bytes).unwrap();
+        assert!(export_tiles(&tree, &dir, "example.com/log", &key).is_err());
+        let reader = TileReader::open(&dir, &key.verifying_key()).unwrap();
+        assert_eq!(reader.checkpoint(), &checkpoint);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
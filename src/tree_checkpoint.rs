@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+
+use crate::merkle_hash::{MerkleDigest, MerkleHash};
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+use crate::node_store::NodeStore;
+
+/// The `TreeCheckpoint` struct is a named state of a `MerkleTree` that the tree can be rewound to.
+///
+/// A checkpoint doesn't copy the tree. Leaves are only appended after it, so it shares every node with the tree and keeps its size and root. Only when a leaf it covers is replaced with `update_leaves` is the old leaf saved in the checkpoint, once.
+///
+/// Checkpoints are kept in memory with the `MerkleTree`, not in its `NodeStore`, so the checkpoints of a tree kept in a `FileStore` are lost when it is opened again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeCheckpoint {
+    name: String,
+    size: usize,
+    root: Option<MerkleHash>,
+    overwritten: BTreeMap<usize, MerkleDigest>,
+}
+
+impl TreeCheckpoint {
+    /// Returns the name of the checkpoint.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the number of leaves the tree had.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the root the tree had, None if it was empty.
+    pub fn root(&self) -> Option<&MerkleHash> {
+        self.root.as_ref()
+    }
+}
+
+impl<S: NodeStore> MerkleTree<S> {
+    /// Names the current state of the tree, in O(1) time and space.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the checkpoint, which must not be taken
+    pub fn checkpoint(&mut self, name: &str) -> Result<&TreeCheckpoint, MerkleTreeError> {
+        if self.checkpoints.iter().any(|c| c.name == name) {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "There already is a checkpoint named {:?}",
+                name
+            )));
+        }
+
+        self.checkpoints.push(TreeCheckpoint {
+            name: name.to_string(),
+            size: self.len(),
+            root: self.root().ok(),
+            overwritten: BTreeMap::new(),
+        });
+        Ok(&self.checkpoints[self.checkpoints.len() - 1])
+    }
+
+    /// Returns the checkpoints of the tree, from the oldest to the newest.
+    pub fn checkpoints(&self) -> &[TreeCheckpoint] {
+        &self.checkpoints
+    }
+
+    /// Restores the leaves and the root the tree had at a checkpoint. The checkpoints created after it are dropped, and the checkpoint is kept so the tree can be rewound to it again.
+    /// If the restored leaves don't give the root of the checkpoint, they are put back and the tree and its checkpoints are left unchanged.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the checkpoint
+    pub fn rewind(&mut self, name: &str) -> Result<(), MerkleTreeError> {
+        let position = self
+            .checkpoints
+            .iter()
+            .position(|c| c.name == name)
+            .ok_or_else(|| {
+                MerkleTreeError::InvalidValue(format!("There is no checkpoint named {:?}", name))
+            })?;
+
+        let checkpoint = self.checkpoints[position].clone();
+        if checkpoint.size > self.len() {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "The tree was truncated to {} leaves after the checkpoint {:?}",
+                self.len(),
+                name
+            )));
+        }
+
+        // Nothing is dropped until the restored leaves give the root of the checkpoint
+        let overwritten: Vec<(usize, MerkleDigest)> = checkpoint.overwritten.into_iter().collect();
+        let current = overwritten
+            .iter()
+            .map(|(index, _)| Ok((*index, self.digest(0, *index)?)))
+            .collect::<Result<Vec<_>, MerkleTreeError>>()?;
+        self.write_digests(&overwritten)?;
+
+        if self.root_at(checkpoint.size).ok() != checkpoint.root {
+            self.write_digests(&current)?;
+            return Err(MerkleTreeError::InvalidHash(format!(
+                "The tree doesn't have the root of the checkpoint {:?} after rewinding",
+                name
+            )));
+        }
+
+        self.checkpoints.truncate(position + 1);
+        self.truncate(checkpoint.size)?;
+        self.checkpoints[position].overwritten.clear();
+        Ok(())
+    }
+
+    /// Saves the leaves that are about to be replaced in the checkpoints that cover them and don't have them yet.
+    pub(crate) fn record_overwritten(
+        &mut self,
+        updates: &[(usize, MerkleDigest)],
+    ) -> Result<(), MerkleTreeError> {
+        for (index, _) in updates {
+            if !self
+                .checkpoints
+                .iter()
+                .any(|c| *index < c.size && !c.overwritten.contains_key(index))
+            {
+                continue;
+            }
+            let old = self.digest(0, *index)?;
+            for checkpoint in self.checkpoints.iter_mut() {
+                if *index < checkpoint.size {
+                    checkpoint.overwritten.entry(*index).or_insert(old);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::merkle_tree::{test_hashes, MerkleTree};
+
+    #[test]
+    fn rewinding_restores_leaves_and_root() {
+        let mut tree = MerkleTree::new_from_hashes(test_hashes("leaf", 20)).unwrap();
+        let root = tree.root().unwrap();
+        tree.checkpoint("before").unwrap();
+        assert!(tree.checkpoint("before").is_err());
+
+        tree.append_hashes(test_hashes("speculative", 13)).unwrap();
+        tree.update_leaves(vec![(3, MerkleTree::get_hash_of(&"changed"))])
+            .unwrap();
+        tree.checkpoint("after").unwrap();
+        tree.update_leaves(vec![(25, MerkleTree::get_hash_of(&"changed again"))])
+            .unwrap();
+        let names: Vec<&str> = tree.checkpoints().iter().map(|c| c.name()).collect();
+        assert_eq!(names, vec!["before", "after"]);
+
+        tree.rewind("before").unwrap();
+        assert_eq!(tree.len(), 20);
+        assert_eq!(tree.root().unwrap(), root);
+        assert_eq!(tree.node(0, 3).unwrap(), test_hashes("leaf", 4)[3]);
+        assert_eq!(tree.checkpoints().len(), 1);
+        assert!(tree.rewind("after").is_err());
+
+        tree.append_hashes(test_hashes("other", 5)).unwrap();
+        tree.rewind("before").unwrap();
+        assert_eq!(tree.root().unwrap(), root);
+    }
+
+    #[test]
+    fn failed_rewinds_leave_the_tree_and_checkpoints_unchanged() {
+        let mut tree = MerkleTree::new_from_hashes(test_hashes("leaf", 20)).unwrap();
+        tree.checkpoint("before").unwrap();
+        tree.update_leaves(vec![(3, MerkleTree::get_hash_of(&"changed"))])
+            .unwrap();
+        tree.append_hashes(test_hashes("speculative", 7)).unwrap();
+        tree.checkpoint("after").unwrap();
+        let root = tree.root().unwrap();
+
+        tree.checkpoints[0].root = Some(MerkleTree::get_hash_of(&"forged"));
+        assert!(tree.rewind("before").is_err());
+        assert_eq!(tree.len(), 27);
+        assert_eq!(tree.root().unwrap(), root);
+        assert_eq!(tree.checkpoints().len(), 2);
+        assert_eq!(tree.checkpoints()[0].overwritten.len(), 1);
+        assert!(tree.checkpoints()[1].overwritten.is_empty());
+
+        tree.checkpoints[0].root = Some(
+            MerkleTree::new_from_hashes(test_hashes("leaf", 20))
+                .unwrap()
+                .root()
+                .unwrap(),
+        );
+        tree.rewind("before").unwrap();
+        assert_eq!(tree.len(), 20);
+        assert_eq!(tree.checkpoints().len(), 1);
+    }
+
+    #[test]
+    fn checkpoints_share_the_nodes_of_the_tree() {
+        let mut tree = MerkleTree::new_from_hashes(test_hashes("leaf", 1000)).unwrap();
+        for i in 0..10 {
+            tree.checkpoint(&format!("batch{}", i)).unwrap();
+            tree.append_hashes(test_hashes(&format!("batch{}-", i), 100))
+                .unwrap();
+        }
+        assert!(tree.checkpoints().iter().all(|c| c.overwritten.is_empty()));
+
+        tree.rewind("batch4").unwrap();
+        assert_eq!(tree.len(), 1400);
+        assert_eq!(
+            tree.root().unwrap(),
+            tree.checkpoints()[4].root().unwrap().clone()
+        );
+        assert_eq!(
+            tree.root_at(1000).unwrap(),
+            tree.checkpoints()[0].root().unwrap().clone()
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use std::ops::Range;
+
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+use crate::node_store::NodeStore;
+
+impl<S: NodeStore> MerkleTree<S> {
+    /// Returns the ranges of leaf indices that differ between two trees, merging adjacent ones.
+    ///
+    /// Both trees are walked from the root and only the subtrees whose hashes differ are descended into, so finding k changed leaves reads O(k log n) nodes. If one tree has more leaves than the other, its extra leaves are reported as changed, and the nodes on the right edge of the shorter tree are descended into without comparing their hashes: a tree ending with a copy of its last leaf has the same hashes there as the tree without the copy.
+    ///
+    /// # Parameters
+    /// - `other`: The tree to compare with, which can be kept in a different store
+    pub fn diff<T: NodeStore>(
+        &self,
+        other: &MerkleTree<T>,
+    ) -> Result<Vec<Range<usize>>, MerkleTreeError> {
+        let mut ranges = vec![];
+        let height = self.height().max(other.height());
+        if height > 0 {
+            self.diff_node(other, height - 1, 0, &mut ranges)?;
+        }
+        Ok(ranges)
+    }
+
+    fn diff_node<T: NodeStore>(
+        &self,
+        other: &MerkleTree<T>,
+        level: usize,
+        index: usize,
+        ranges: &mut Vec<Range<usize>>,
+    ) -> Result<(), MerkleTreeError> {
+        let in_self = index < self.level_len(level);
+        let in_other = index < other.level_len(level);
+
+        if !in_self && !in_other {
+            return Ok(());
+        }
+        let shorter = self.len().min(other.len());
+        let on_edge = self.len() != other.len() && ((index + 1) << level) > shorter;
+        if in_self
+            && in_other
+            && !on_edge
+            && self.digest(level, index)? == other.digest(level, index)?
+        {
+            return Ok(());
+        }
+        if in_self != in_other && (index << level) >= shorter {
+            let end = ((index + 1) << level).min(self.len().max(other.len()));
+            MerkleTree::<S>::push_range(ranges, (index << level)..end);
+            return Ok(());
+        }
+
+        if level == 0 {
+            MerkleTree::<S>::push_range(ranges, index..index + 1);
+            return Ok(());
+        }
+        self.diff_node(other, level - 1, 2 * index, ranges)?;
+        self.diff_node(other, level - 1, 2 * index + 1, ranges)
+    }
+
+    /// Adds a range after the last one, merging them if they are adjacent.
+    fn push_range(ranges: &mut Vec<Range<usize>>, range: Range<usize>) {
+        match ranges.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => ranges.push(range),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::file_store::FileStore;
+    use crate::merkle_tree::{test_hashes, MerkleTree};
+
+    #[test]
+    fn diff_finds_the_changed_ranges() {
+        let original = test_hashes("leaf", 1000);
+        let mut changed = original.clone();
+        for i in [3, 4, 5, 500, 999] {
+            changed[i] = MerkleTree::get_hash_of(&format!("changed{}", i));
+        }
+
+        let a = MerkleTree::new_from_hashes(original.clone()).unwrap();
+        let b = MerkleTree::new_from_hashes(changed).unwrap();
+        assert_eq!(a.diff(&b).unwrap(), vec![3..6, 500..501, 999..1000]);
+        assert_eq!(b.diff(&a).unwrap(), vec![3..6, 500..501, 999..1000]);
+        assert!(a.diff(&a).unwrap().is_empty());
+
+        let shorter = MerkleTree::new_from_hashes(original[..700].to_vec()).unwrap();
+        assert_eq!(a.diff(&shorter).unwrap(), vec![700..1000]);
+        let lower = MerkleTree::new_from_hashes(original[..300].to_vec()).unwrap();
+        assert_eq!(lower.diff(&a).unwrap(), vec![300..1000]);
+    }
+
+    #[test]
+    fn duplicated_last_leaves_are_reported() {
+        let mut leaves = test_hashes("leaf", 4);
+        leaves[3] = leaves[2].clone();
+        let short = MerkleTree::new_from_hashes(leaves[..3].to_vec()).unwrap();
+        let long = MerkleTree::new_from_hashes(leaves).unwrap();
+        assert_eq!(short.root().unwrap(), long.root().unwrap());
+
+        assert_eq!(short.diff(&long).unwrap(), vec![3..4]);
+        assert_eq!(long.diff(&short).unwrap(), vec![3..4]);
+    }
+
+    #[test]
+    fn trees_in_different_stores_are_compared() {
+        let dir = std::env::temp_dir().join(format!("merkle-diff-{}", std::process::id()));
+        let mut leaves = test_hashes("leaf", 300);
+        let mut stored = MerkleTree::from_store(FileStore::open(&dir).unwrap());
+        stored.append_hashes(leaves.clone()).unwrap();
+
+        leaves[42] = MerkleTree::get_hash_of(&"changed");
+        let memory = MerkleTree::new_from_hashes(leaves).unwrap();
+        assert_eq!(memory.diff(&stored).unwrap(), vec![42..43]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+
+/// The HashAlgorithm enum represents the hash function used for the leaves and the nodes of a tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HashAlgorithm {
+    #[serde(rename = "sha3-256")]
+    Sha3_256,
+    #[serde(rename = "sha-256")]
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Returns the name of the algorithm, as written in manifests and signed tree heads.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha3_256 => "sha3-256",
+            HashAlgorithm::Sha256 => "sha-256",
+        }
+    }
+}
+
+/// The TreeMode enum represents how the nodes of a tree are built from their children.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TreeMode {
+    /// Every node is the hash of the hex representation of its children, and the last node of a level with an odd number of nodes is hashed with itself, as `MerkleTree` does.
+    #[serde(rename = "hex-pairs-duplicate-odd")]
+    HexPairsDuplicateOdd,
+    /// Leaves are hashed as `H(0x00 || leaf)` and nodes as `H(0x01 || left || right)`, and a tree of n leaves is split after the largest power of two smaller than n, as RFC 6962 does. Used by the tlog tiles.
+    #[serde(rename = "rfc6962")]
+    Rfc6962,
+}
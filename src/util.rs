@@ -1,7 +1,17 @@
-use crate::{cli::CLI, merkle_tree::MerkleTree};
+use crate::{cli::CLI, merkle_tree::MerkleTree, merkle_tree_error::MerkleTreeError};
 
 /// Runs an example from a file.
 pub fn run_example_from_path(path: &str) {
+    run_example(path, MerkleTree::new_from_hashes);
+}
+
+/// Runs an example from a file whose elements are hashed before being added to the tree, as with the `--hash` flag of `tree create`.
+pub fn run_hashed_example_from_path(path: &str) {
+    run_example(path, MerkleTree::new_from_hashables);
+}
+
+/// Builds a tree from the elements of a file and runs the CLI on it.
+fn run_example(path: &str, build: fn(Vec<String>) -> Result<MerkleTree, MerkleTreeError>) {
     let elements = match CLI::process_file(path) {
         Ok(elements) => elements,
         Err(e) => {
@@ -10,7 +20,7 @@ pub fn run_example_from_path(path: &str) {
         }
     };
 
-    let tree = match MerkleTree::new_from_hashes(elements) {
+    let tree = match build(elements) {
         Ok(tree) => tree,
         Err(e) => {
             println!("Failed to build the Merkle Tree: {:?}", e);
@@ -0,0 +1,298 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use crate::consistency_proof::ConsistencyProof;
+use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree_error::MerkleTreeError;
+use crate::signed_tree_head::{self, SignedTreeHead};
+
+/// Prefix of the bytes signed by a witness, so cosignatures can't be mistaken for signatures of the log.
+const COSIGNED_PREFIX: &str = "rusty-merkle-tree cosignature v1";
+
+/// The signature of a witness over a signed tree head, stating that the head is consistent with every head the witness saw before.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Cosignature {
+    /// The public key of the witness, in hex.
+    pub witness: String,
+    /// The signature, in hex.
+    pub signature: String,
+}
+
+/// Evidence that a log signed two heads that can't belong to the same append-only tree.
+///
+/// Either both heads have the same size and different roots, or `proof` links the root of the newer head to a root of the older size that is not the one the log signed. Since the proof is checked against a signed root, anyone holding the key of the log can verify the alarm.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForkAlarm {
+    pub older: SignedTreeHead,
+    pub newer: SignedTreeHead,
+    pub proof: Option<ConsistencyProof>,
+}
+
+/// What a witness does with a new signed tree head.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WitnessOutcome {
+    /// The head is consistent with the last one, and is now the last one.
+    Cosigned(Cosignature),
+    /// The head conflicts with the last one, which is kept.
+    Fork(ForkAlarm),
+}
+
+/// The `Witness` struct follows the signed tree heads of a log and cosigns them, so clients that see a cosigned head know the log showed the witness the same tree.
+///
+/// The witness keeps the last head it cosigned. A newer head is only cosigned along with a consistency proof from the last one, so the log can't rewrite the leaves it already signed, and heads that contradict the last one raise a `ForkAlarm`.
+#[derive(Debug)]
+pub struct Witness {
+    key: SigningKey,
+    log_key: VerifyingKey,
+    latest: Option<SignedTreeHead>,
+}
+
+impl Cosignature {
+    /// Returns the bytes signed by a witness for a head.
+    fn signed_bytes(sth: &SignedTreeHead) -> Vec<u8> {
+        let mut bytes = format!("{}\n", COSIGNED_PREFIX).into_bytes();
+        bytes.extend(sth.head.signed_bytes());
+        bytes
+    }
+
+    /// Checks that the cosignature is a signature of the head by the given witness.
+    pub fn verify(
+        &self,
+        sth: &SignedTreeHead,
+        witness: &VerifyingKey,
+    ) -> Result<(), MerkleTreeError> {
+        if self.witness != MerkleTree::bytes_to_hex(witness.as_bytes()) {
+            return Err(MerkleTreeError::InvalidSignature(
+                "The cosignature is from another witness".to_string(),
+            ));
+        }
+        let bytes: [u8; 64] = signed_tree_head::hex_to_bytes(&self.signature)?
+            .try_into()
+            .map_err(|_| {
+                MerkleTreeError::InvalidSignature("A signature has 64 bytes".to_string())
+            })?;
+
+        witness
+            .verify(
+                &Cosignature::signed_bytes(sth),
+                &Signature::from_bytes(&bytes),
+            )
+            .map_err(|_| {
+                MerkleTreeError::InvalidSignature(
+                    "The tree head was not cosigned by the witness".to_string(),
+                )
+            })
+    }
+}
+
+impl ForkAlarm {
+    /// Checks that both heads are signed by the log and that they conflict.
+    pub fn verify(&self, log_key: &VerifyingKey) -> bool {
+        if self.older.verify(log_key).is_err() || self.newer.verify(log_key).is_err() {
+            return false;
+        }
+
+        let (older, newer) = (&self.older.head, &self.newer.head);
+        match &self.proof {
+            None => older.tree_size == newer.tree_size && older.root != newer.root,
+            Some(proof) => match proof.roots() {
+                Some((old_root, new_root)) => {
+                    proof.old_size as u64 == older.tree_size
+                        && proof.new_size as u64 == newer.tree_size
+                        && new_root == newer.root
+                        && old_root != older.root
+                }
+                None => false,
+            },
+        }
+    }
+}
+
+impl Witness {
+    /// Creates a witness with its own key, following the log with the given key.
+    pub fn new(key: SigningKey, log_key: VerifyingKey) -> Witness {
+        Witness {
+            key,
+            log_key,
+            latest: None,
+        }
+    }
+
+    /// Creates a witness that already cosigned the given head, e.g. one read from the file where it was kept.
+    pub fn with_latest(
+        key: SigningKey,
+        log_key: VerifyingKey,
+        latest: SignedTreeHead,
+    ) -> Result<Witness, MerkleTreeError> {
+        latest.verify(&log_key)?;
+        Ok(Witness {
+            key,
+            log_key,
+            latest: Some(latest),
+        })
+    }
+
+    /// Returns the public key of the witness.
+    pub fn verifying_key(&self) -> VerifyingKey {
+        self.key.verifying_key()
+    }
+
+    /// Returns the last head the witness cosigned.
+    pub fn latest(&self) -> Option<&SignedTreeHead> {
+        self.latest.as_ref()
+    }
+
+    /// Cosigns a head.
+    fn cosign(&self, sth: &SignedTreeHead) -> Cosignature {
+        let signature = self.key.sign(&Cosignature::signed_bytes(sth));
+        Cosignature {
+            witness: MerkleTree::bytes_to_hex(self.key.verifying_key().as_bytes()),
+            signature: MerkleTree::bytes_to_hex(&signature.to_bytes()),
+        }
+    }
+
+    /// Submits a new head of the log to the witness.
+    ///
+    /// The first head is trusted. Then, a head of the same size is cosigned if it has the same root, and a bigger one if `proof` proves it is consistent with the last head. Heads that are signed with another key, smaller than the last one, or come with a proof that doesn't lead to their root are rejected with an error.
+    ///
+    /// # Parameters
+    /// - `sth`: The new signed tree head
+    /// - `proof`: A consistency proof from the last head the witness cosigned to the new one
+    pub fn submit(
+        &mut self,
+        sth: SignedTreeHead,
+        proof: Option<&ConsistencyProof>,
+    ) -> Result<WitnessOutcome, MerkleTreeError> {
+        sth.verify(&self.log_key)?;
+        let latest = match &self.latest {
+            Some(latest) => latest,
+            None => {
+                let cosignature = self.cosign(&sth);
+                self.latest = Some(sth);
+                return Ok(WitnessOutcome::Cosigned(cosignature));
+            }
+        };
+
+        let (old, new) = (&latest.head, &sth.head);
+        if new.tree_size < old.tree_size {
+            return Err(MerkleTreeError::InvalidValue(format!(
+                "The head has {} leaves, fewer than the {} of the last one",
+                new.tree_size, old.tree_size
+            )));
+        }
+        if new.tree_size == old.tree_size {
+            if new.root != old.root {
+                return Ok(WitnessOutcome::Fork(ForkAlarm {
+                    older: latest.clone(),
+                    newer: sth,
+                    proof: None,
+                }));
+            }
+            return Ok(WitnessOutcome::Cosigned(self.cosign(&sth)));
+        }
+
+        let proof = proof.ok_or_else(|| {
+            MerkleTreeError::InvalidValue("A bigger head needs a consistency proof".to_string())
+        })?;
+        let roots = match proof.old_size as u64 == old.tree_size
+            && proof.new_size as u64 == new.tree_size
+        {
+            true => proof.roots(),
+            false => None,
+        };
+        match roots {
+            Some((old_root, new_root)) if new_root == new.root => {
+                if old_root != old.root {
+                    return Ok(WitnessOutcome::Fork(ForkAlarm {
+                        older: latest.clone(),
+                        newer: sth,
+                        proof: Some(proof.clone()),
+                    }));
+                }
+                let cosignature = self.cosign(&sth);
+                self.latest = Some(sth);
+                Ok(WitnessOutcome::Cosigned(cosignature))
+            }
+            _ => Err(MerkleTreeError::InvalidValue(
+                "The consistency proof doesn't lead from the last head to the new one".to_string(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::merkle_tree::{test_hashes, MerkleTree};
+    use crate::signed_tree_head::{generate_key, TreeHead};
+    use crate::witness::{Witness, WitnessOutcome};
+
+    #[test]
+    fn consistent_heads_are_cosigned() {
+        let log_key = generate_key();
+        let mut witness = Witness::new(generate_key(), log_key.verifying_key());
+        let leaves = test_hashes("leaf", 30);
+
+        let old = MerkleTree::new_from_hashes(leaves[..10].to_vec()).unwrap();
+        let old_sth = TreeHead::new(&old).unwrap().sign(&log_key);
+        assert!(matches!(
+            witness.submit(old_sth.clone(), None).unwrap(),
+            WitnessOutcome::Cosigned(_)
+        ));
+
+        let new = MerkleTree::new_from_hashes(leaves).unwrap();
+        let new_sth = TreeHead::new(&new).unwrap().sign(&log_key);
+        assert!(witness.submit(new_sth.clone(), None).is_err());
+
+        let proof = new.consistency_proof(10).unwrap();
+        match witness.submit(new_sth.clone(), Some(&proof)).unwrap() {
+            WitnessOutcome::Cosigned(cosignature) => {
+                assert!(cosignature
+                    .verify(&new_sth, &witness.verifying_key())
+                    .is_ok());
+                assert!(cosignature
+                    .verify(&old_sth, &witness.verifying_key())
+                    .is_err());
+            }
+            outcome => panic!("unexpected {:?}", outcome),
+        }
+        assert_eq!(witness.latest(), Some(&new_sth));
+        assert!(witness.submit(old_sth, None).is_err());
+
+        let forged = TreeHead::new(&new).unwrap().sign(&generate_key());
+        assert!(witness.submit(forged, None).is_err());
+    }
+
+    #[test]
+    fn conflicting_heads_raise_verifiable_alarms() {
+        let log_key = generate_key();
+        let mut leaves = test_hashes("leaf", 30);
+        let old = MerkleTree::new_from_hashes(leaves[..10].to_vec()).unwrap();
+        let old_sth = TreeHead::new(&old).unwrap().sign(&log_key);
+
+        leaves[3] = MerkleTree::get_hash_of(&"rewritten");
+        let forked = MerkleTree::new_from_hashes(leaves[..10].to_vec()).unwrap();
+        let mut witness =
+            Witness::with_latest(generate_key(), log_key.verifying_key(), old_sth.clone()).unwrap();
+        match witness
+            .submit(TreeHead::new(&forked).unwrap().sign(&log_key), None)
+            .unwrap()
+        {
+            WitnessOutcome::Fork(alarm) => assert!(alarm.verify(&log_key.verifying_key())),
+            outcome => panic!("unexpected {:?}", outcome),
+        }
+
+        let new = MerkleTree::new_from_hashes(leaves).unwrap();
+        let new_sth = TreeHead::new(&new).unwrap().sign(&log_key);
+        let proof = new.consistency_proof(10).unwrap();
+        match witness.submit(new_sth, Some(&proof)).unwrap() {
+            WitnessOutcome::Fork(alarm) => {
+                assert!(alarm.verify(&log_key.verifying_key()));
+                assert!(!alarm.verify(&generate_key().verifying_key()));
+            }
+            outcome => panic!("unexpected {:?}", outcome),
+        }
+        assert_eq!(witness.latest(), Some(&old_sth));
+    }
+}
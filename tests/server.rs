@@ -0,0 +1,148 @@
+#![cfg(feature = "server")]
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use rusty_merkle_tree::consistency_proof::ConsistencyProof;
+use rusty_merkle_tree::merkle_tree::MerkleTree;
+use rusty_merkle_tree::proof_of_inclusion::ProofOfInclusion;
+use rusty_merkle_tree::server::TreeServer;
+
+/// Sends a request and returns the status code and JSON body of the response.
+fn request(address: SocketAddr, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+    let body = body.map(|b| b.to_string()).unwrap_or_default();
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        body.len(),
+        body
+    )
+    .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, serde_json::from_str(body).unwrap())
+}
+
+#[test]
+fn the_api_creates_appends_and_proves() {
+    let tree = MerkleTree::new_from_hashables(vec!["a"]).unwrap();
+    let server = TreeServer::start("127.0.0.1:0", tree, 2).unwrap();
+    let address = server.address();
+
+    let elements: Vec<String> = (0..10).map(|i| format!("leaf{}", i)).collect();
+    let (status, created) = request(
+        address,
+        "POST",
+        "/tree",
+        Some(json!({ "elements": elements[..6] })),
+    );
+    assert_eq!(status, 200);
+    assert_eq!(created["size"], 6);
+    let old_root = created["root"].as_str().unwrap().to_string();
+
+    let (_, appended) = request(
+        address,
+        "POST",
+        "/tree/leaves",
+        Some(json!({ "elements": elements[6..] })),
+    );
+    let expected = MerkleTree::new_from_hashables(elements.clone()).unwrap();
+    assert_eq!(appended["size"], 10);
+    assert_eq!(appended["root"], json!(expected.root().unwrap()));
+    assert_eq!(request(address, "GET", "/tree/root", None).1, appended);
+
+    let leaf = MerkleTree::get_hash_of(&"leaf7");
+    let encoded: String = leaf.bytes().map(|b| format!("%{:02X}", b)).collect();
+    for path in [
+        "/tree/proof?index=7".to_string(),
+        format!("/tree/proof?value={}", leaf),
+        format!("/tree/proof?%76alue={}", encoded),
+    ] {
+        let (status, body) = request(address, "GET", &path, None);
+        assert_eq!(status, 200);
+        assert_eq!(body["index"], 7);
+        let proof: ProofOfInclusion = serde_json::from_value(body["proof"].clone()).unwrap();
+        assert_eq!(proof.leaf(), &leaf);
+        assert!(proof.verify(&expected.root().unwrap()));
+    }
+
+    let verify = |body| request(address, "POST", "/tree/verify", Some(body)).1["included"].clone();
+    assert_eq!(verify(json!({ "leaf": leaf, "index": 7 })), json!(true));
+    assert_eq!(verify(json!({ "leaf": leaf, "index": 3 })), json!(false));
+    assert_eq!(verify(json!({ "leaf": leaf })), json!(true));
+
+    let (_, body) = request(address, "GET", "/tree/consistency?old_size=6", None);
+    let proof: ConsistencyProof = serde_json::from_value(body).unwrap();
+    assert!(proof.verify(&old_root, &expected.root().unwrap()));
+
+    assert_eq!(request(address, "GET", "/tree/proof?index=10", None).0, 404);
+    assert_eq!(
+        request(address, "GET", "/tree/consistency?old_size=11", None).0,
+        400
+    );
+    assert_eq!(
+        request(address, "POST", "/tree/leaves", Some(json!("x"))).0,
+        400
+    );
+    assert_eq!(request(address, "GET", "/tree/proof?value=%4", None).0, 400);
+    assert_eq!(request(address, "GET", "/nowhere", None).0, 404);
+
+    server.shutdown();
+}
+
+#[test]
+fn only_loopback_addresses_are_served() {
+    assert!(TreeServer::bind("0.0.0.0:0").is_err());
+    assert!(TreeServer::bind("not an address").is_err());
+    let server = TreeServer::bind("localhost:0").unwrap();
+    assert!(server.address().ip().is_loopback());
+}
+
+#[test]
+fn readers_run_concurrently_with_a_writer() {
+    let tree = MerkleTree::new_from_hashables(vec!["first"]).unwrap();
+    let server = TreeServer::start("127.0.0.1:0", tree, 4).unwrap();
+    let address = server.address();
+
+    let writer = thread::spawn(move || {
+        for i in 0..20 {
+            let hash = MerkleTree::get_hash_of(&format!("leaf{}", i));
+            let (status, _) = request(
+                address,
+                "POST",
+                "/tree/leaves",
+                Some(json!({ "hashes": [hash] })),
+            );
+            assert_eq!(status, 200);
+        }
+    });
+    let readers: Vec<_> = (0..3)
+        .map(|_| {
+            thread::spawn(move || {
+                for _ in 0..20 {
+                    let (status, body) = request(address, "GET", "/tree/proof?index=0", None);
+                    assert_eq!(status, 200);
+                    let proof: ProofOfInclusion =
+                        serde_json::from_value(body["proof"].clone()).unwrap();
+                    assert!(proof.verify(&body["root"].as_str().unwrap().to_string()));
+                }
+            })
+        })
+        .collect();
+
+    writer.join().unwrap();
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    assert_eq!(server.tree().read().unwrap().len(), 21);
+    server.shutdown();
+}